@@ -1,77 +1,1721 @@
+use crate::interpreter::Bloodbath;
+use crate::interpreter::EvaluationResult;
+use crate::interpreter::RuntimeError;
+use crate::object::Arity;
+use crate::object::FunctionImplementation;
 use crate::object::Object;
+use crate::random::Random;
+use crate::reader::Reader;
+use crate::reader::Token;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::Write;
 
-pub fn add(args: Vec<Object>) -> Object {
+fn expected(function_name: &str, description: &str) -> RuntimeError {
+    RuntimeError::InvalidArgument(format!("`{}` expects {}", function_name, description))
+}
+
+fn overflow(function_name: &str) -> RuntimeError {
+    RuntimeError::InvalidArgument(format!("`{}`: integer overflow", function_name))
+}
+
+pub fn add(args: Vec<Object>) -> EvaluationResult {
+    args.iter()
+        .try_fold(Object::Integer(0), |sum, arg| add_pair([&sum, arg]))
+}
+
+fn add_pair(args: [&Object; 2]) -> EvaluationResult {
+    numeric_binop(
+        "+",
+        "numbers",
+        args,
+        |a, b| a.checked_add(b).map(Object::Integer),
+        |a, b| a + b,
+    )
+}
+
+pub fn sub(args: Vec<Object>) -> EvaluationResult {
+    numeric_binop(
+        "-",
+        "two numbers",
+        [&args[0], &args[1]],
+        |a, b| a.checked_sub(b).map(Object::Integer),
+        |a, b| a - b,
+    )
+}
+
+pub fn neg(args: Vec<Object>) -> EvaluationResult {
     if let Some(a) = args[0].get_integer() {
-        if let Some(b) = args[1].get_integer() {
-            return Object::Integer(a + b);
-        } else if let Some(b) = args[1].get_float() {
-            return Object::Float(a as f64 + b);
-        }
-    } else if let Some(a) = args[0].get_float() {
-        if let Some(b) = args[1].get_integer() {
-            return Object::Float(a + b as f64);
-        } else if let Some(b) = args[1].get_float() {
-            return Object::Float(a + b);
+        return a
+            .checked_neg()
+            .map(Object::Integer)
+            .ok_or_else(|| overflow("neg"));
+    } else if let Some(a) = args[0].get_number() {
+        return Ok(Object::Float(-a));
+    }
+
+    Err(expected("neg", "a number"))
+}
+
+pub fn mul(args: Vec<Object>) -> EvaluationResult {
+    args.iter()
+        .try_fold(Object::Integer(1), |product, arg| mul_pair([&product, arg]))
+}
+
+fn mul_pair(args: [&Object; 2]) -> EvaluationResult {
+    numeric_binop(
+        "*",
+        "numbers",
+        args,
+        |a, b| a.checked_mul(b).map(Object::Integer),
+        |a, b| a * b,
+    )
+}
+
+// `/` always gives a float, even for integers that divide evenly. Use `//` to
+// stay with integers.
+pub fn div(args: Vec<Object>) -> EvaluationResult {
+    // Floats divide into infinity or NaN, but integers are still rejected.
+    if let (Some(_), Some(0)) = (args[0].get_integer(), args[1].get_integer()) {
+        return Err(expected("/", "a non-zero divisor"));
+    }
+
+    numeric_binop(
+        "/",
+        "two numbers",
+        [&args[0], &args[1]],
+        |a, b| Some(Object::Float(a as f64 / b as f64)),
+        |a, b| a / b,
+    )
+}
+
+// Integer division that rounds the quotient down, so `// -7 2` is -4 rather
+// than -3.
+pub fn floor_div(args: Vec<Object>) -> EvaluationResult {
+    let (a, b) = match (args[0].get_integer(), args[1].get_integer()) {
+        (Some(_), Some(0)) => return Err(expected("//", "a non-zero divisor")),
+        (Some(a), Some(b)) => (a, b),
+        _ => return Err(expected("//", "two integers")),
+    };
+
+    // Only the smallest integer divided by -1 overflows.
+    match a.checked_div(b) {
+        Some(quotient) if a % b != 0 && (a < 0) != (b < 0) => Ok(Object::Integer(quotient - 1)),
+        Some(quotient) => Ok(Object::Integer(quotient)),
+        None => Err(overflow("//")),
+    }
+}
+
+pub fn min(args: Vec<Object>) -> EvaluationResult {
+    numeric_binop(
+        "min",
+        "two numbers",
+        [&args[0], &args[1]],
+        |a, b| Some(Object::Integer(a.min(b))),
+        |a, b| propagate_nan(a, b, f64::min),
+    )
+}
+
+pub fn max(args: Vec<Object>) -> EvaluationResult {
+    numeric_binop(
+        "max",
+        "two numbers",
+        [&args[0], &args[1]],
+        |a, b| Some(Object::Integer(a.max(b))),
+        |a, b| propagate_nan(a, b, f64::max),
+    )
+}
+
+// `f64::min` and `f64::max` ignore a NaN argument, see `equal` for why it's
+// kept instead.
+fn propagate_nan(a: f64, b: f64, f: fn(f64, f64) -> f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        f64::NAN
+    } else {
+        f(a, b)
+    }
+}
+
+// Applies `integers` if both arguments are integers, and `floats` otherwise,
+// promoting an integer argument to a float if needed. `integers` returns `None`
+// on overflow.
+fn numeric_binop(
+    function_name: &str,
+    description: &str,
+    args: [&Object; 2],
+    integers: impl Fn(i64, i64) -> Option<Object>,
+    floats: impl Fn(f64, f64) -> f64,
+) -> EvaluationResult {
+    if let (Some(a), Some(b)) = (args[0].get_integer(), args[1].get_integer()) {
+        return integers(a, b).ok_or_else(|| overflow(function_name));
+    }
+
+    match (args[0].get_number(), args[1].get_number()) {
+        (Some(a), Some(b)) => Ok(Object::Float(floats(a, b))),
+        _ => Err(expected(function_name, description)),
+    }
+}
+
+// Rounds half to even, so `round 2.5` is 2 and `round 3.5` is 4. Integers are
+// returned unchanged.
+pub fn round(args: Vec<Object>) -> EvaluationResult {
+    let places = match args.len() {
+        1 => 0,
+        2 => match args[1].get_integer() {
+            Some(places) if (0..=i32::MAX as i64).contains(&places) => places as i32,
+            _ => return Err(expected("round", "a non-negative number of decimal places")),
+        },
+        _ => return Err(expected("round", "a number and optional decimal places")),
+    };
+
+    if let Some(value) = args[0].get_integer() {
+        return Ok(Object::Integer(value));
+    }
+
+    let value = args[0]
+        .get_float()
+        .ok_or_else(|| expected("round", "a number"))?;
+
+    if places == 0 {
+        return Ok(Object::Float(value.round_ties_even()));
+    }
+
+    let scale = 10f64.powi(places);
+    let rounded = (value * scale).round_ties_even() / scale;
+
+    // Scaling huge values overflows; they have no fractional digits anyway.
+    if rounded.is_finite() {
+        Ok(Object::Float(rounded))
+    } else {
+        Ok(Object::Float(value))
+    }
+}
+
+fn float_function(function_name: &str, args: &[Object], f: fn(f64) -> f64) -> EvaluationResult {
+    match args[0].get_number() {
+        Some(value) => Ok(Object::Float(f(value))),
+        None => Err(expected(function_name, "a number")),
+    }
+}
+
+pub fn sin(args: Vec<Object>) -> EvaluationResult {
+    float_function("sin", &args, f64::sin)
+}
+
+pub fn cos(args: Vec<Object>) -> EvaluationResult {
+    float_function("cos", &args, f64::cos)
+}
+
+pub fn tan(args: Vec<Object>) -> EvaluationResult {
+    float_function("tan", &args, f64::tan)
+}
+
+// Values outside of the domain are rejected rather than quietly turned into NaN.
+pub fn log(args: Vec<Object>) -> EvaluationResult {
+    let positive = |arg: &Object| arg.get_number().filter(|value| *value > 0.0);
+
+    let value = positive(&args[0]).ok_or_else(|| expected("log", "a positive number"))?;
+
+    match args.len() {
+        1 => Ok(Object::Float(value.ln())),
+        2 => match positive(&args[1]) {
+            Some(base) if base != 1.0 => Ok(Object::Float(value.log(base))),
+            _ => Err(expected("log", "a positive base other than 1")),
+        },
+        _ => Err(expected("log", "a number and an optional base")),
+    }
+}
+
+pub fn exp(args: Vec<Object>) -> EvaluationResult {
+    float_function("exp", &args, f64::exp)
+}
+
+// Compares two numbers, promoting to floats when their types differ. Returns
+// `None` if they can't be ordered, i.e. if either of them is NaN.
+fn compare(function_name: &str, args: &[Object]) -> Result<Option<Ordering>, RuntimeError> {
+    if let (Some(a), Some(b)) = (args[0].get_integer(), args[1].get_integer()) {
+        return Ok(Some(a.cmp(&b)));
+    }
+
+    match (args[0].get_number(), args[1].get_number()) {
+        (Some(a), Some(b)) => Ok(a.partial_cmp(&b)),
+        _ => Err(expected(function_name, "two numbers")),
+    }
+}
+
+// The NaN policy: NaN is equal to itself under both `=` and `eq?`, so that
+// equality stays reflexive. `min` and `max` return NaN if either argument is
+// NaN instead of dropping it. The ordering comparisons treat NaN as
+// incomparable and return false.
+pub fn equal(args: Vec<Object>) -> EvaluationResult {
+    if let (Some(a), Some(b)) = (args[0].get_float(), args[1].get_float()) {
+        if a.is_nan() && b.is_nan() {
+            return Ok(Object::Boolean(true));
         }
     }
 
-    Object::Noop
+    let ordering = compare("=", &args)?;
+    Ok(Object::Boolean(ordering == Some(Ordering::Equal)))
+}
+
+pub fn less(args: Vec<Object>) -> EvaluationResult {
+    let ordering = compare("<", &args)?;
+    Ok(Object::Boolean(ordering == Some(Ordering::Less)))
+}
+
+pub fn greater(args: Vec<Object>) -> EvaluationResult {
+    let ordering = compare(">", &args)?;
+    Ok(Object::Boolean(ordering == Some(Ordering::Greater)))
+}
+
+pub fn less_or_equal(args: Vec<Object>) -> EvaluationResult {
+    let ordering = compare("<=", &args)?;
+    Ok(Object::Boolean(matches!(
+        ordering,
+        Some(Ordering::Less | Ordering::Equal)
+    )))
+}
+
+pub fn greater_or_equal(args: Vec<Object>) -> EvaluationResult {
+    let ordering = compare(">=", &args)?;
+    Ok(Object::Boolean(matches!(
+        ordering,
+        Some(Ordering::Greater | Ordering::Equal)
+    )))
+}
+
+// Integers are never NaN or infinite, so promoting them is harmless.
+pub fn is_nan(args: Vec<Object>) -> EvaluationResult {
+    match args[0].get_number() {
+        Some(value) => Ok(Object::Boolean(value.is_nan())),
+        None => Err(expected("nan?", "a number")),
+    }
+}
+
+pub fn is_infinite(args: Vec<Object>) -> EvaluationResult {
+    match args[0].get_number() {
+        Some(value) => Ok(Object::Boolean(value.is_infinite())),
+        None => Err(expected("infinite?", "a number")),
+    }
+}
+
+// Unlike `=`, these compare any two objects without converting between types,
+// so `eq? 1 1.0` is false.
+pub fn eq(args: Vec<Object>) -> EvaluationResult {
+    Ok(Object::Boolean(args[0] == args[1]))
+}
+
+pub fn ne(args: Vec<Object>) -> EvaluationResult {
+    Ok(Object::Boolean(args[0] != args[1]))
 }
 
-pub fn sub(args: Vec<Object>) -> Object {
+fn integer_pair(function_name: &str, args: &[Object]) -> Result<(i64, i64), RuntimeError> {
     if let Some(a) = args[0].get_integer() {
         if let Some(b) = args[1].get_integer() {
-            return Object::Integer(a - b);
-        } else if let Some(b) = args[1].get_float() {
-            return Object::Float(a as f64 - b);
+            return Ok((a, b));
         }
-    } else if let Some(a) = args[0].get_float() {
-        if let Some(b) = args[1].get_integer() {
-            return Object::Float(a - b as f64);
-        } else if let Some(b) = args[1].get_float() {
-            return Object::Float(a - b);
+    }
+
+    Err(expected(function_name, "two integers"))
+}
+
+pub fn band(args: Vec<Object>) -> EvaluationResult {
+    let (a, b) = integer_pair("band", &args)?;
+    Ok(Object::Integer(a & b))
+}
+
+pub fn bor(args: Vec<Object>) -> EvaluationResult {
+    let (a, b) = integer_pair("bor", &args)?;
+    Ok(Object::Integer(a | b))
+}
+
+pub fn bxor(args: Vec<Object>) -> EvaluationResult {
+    let (a, b) = integer_pair("bxor", &args)?;
+    Ok(Object::Integer(a ^ b))
+}
+
+fn shift_amount(function_name: &str, amount: i64) -> Result<u32, RuntimeError> {
+    if (0..64).contains(&amount) {
+        Ok(amount as u32)
+    } else {
+        Err(RuntimeError::InvalidArgument(format!(
+            "`{}` cannot shift by {} bits",
+            function_name, amount
+        )))
+    }
+}
+
+pub fn shl(args: Vec<Object>) -> EvaluationResult {
+    let (a, b) = integer_pair("shl", &args)?;
+    Ok(Object::Integer(a << shift_amount("shl", b)?))
+}
+
+pub fn shr(args: Vec<Object>) -> EvaluationResult {
+    let (a, b) = integer_pair("shr", &args)?;
+    Ok(Object::Integer(a >> shift_amount("shr", b)?))
+}
+
+pub fn concat(args: Vec<Object>) -> EvaluationResult {
+    if let Some(a) = args[0].get_string() {
+        if let Some(b) = args[1].get_string() {
+            return Ok(Object::String(format!("{}{}", a, b).into()));
         }
     }
 
-    Object::Noop
+    Err(expected("concat", "two strings"))
 }
 
-pub fn mul(args: Vec<Object>) -> Object {
-    if let Some(a) = args[0].get_integer() {
-        if let Some(b) = args[1].get_integer() {
-            return Object::Integer(a * b);
-        } else if let Some(b) = args[1].get_float() {
-            return Object::Float(a as f64 * b);
+pub fn length(args: Vec<Object>) -> EvaluationResult {
+    if let Some(string) = args[0].get_string() {
+        return Ok(Object::Integer(string.chars().count() as i64));
+    } else if let Some(items) = args[0].get_list() {
+        return Ok(Object::Integer(items.len() as i64));
+    }
+
+    Err(expected("length", "a string or a list"))
+}
+
+pub fn char_at(args: Vec<Object>) -> EvaluationResult {
+    if let Some(string) = args[0].get_string() {
+        if let Some(index) = args[1].get_integer() {
+            let length = string.chars().count() as i64;
+
+            if index < 0 || index >= length {
+                return Err(RuntimeError::InvalidArgument(format!(
+                    "Index {} is out of range for a string of length {}",
+                    index, length
+                )));
+            }
+
+            return Ok(Object::Char(string.chars().nth(index as usize).unwrap()));
         }
-    } else if let Some(a) = args[0].get_float() {
-        if let Some(b) = args[1].get_integer() {
-            return Object::Float(a * b as f64);
-        } else if let Some(b) = args[1].get_float() {
-            return Object::Float(a * b);
+    }
+
+    Err(expected("char-at", "a string and an integer"))
+}
+
+pub fn string_to_list(args: Vec<Object>) -> EvaluationResult {
+    if let Some(string) = args[0].get_string() {
+        return Ok(Object::List(
+            string.chars().map(Object::Char).collect::<Vec<_>>().into(),
+        ));
+    }
+
+    Err(expected("string->list", "a string"))
+}
+
+pub fn list_to_string(args: Vec<Object>) -> EvaluationResult {
+    if let Some(items) = args[0].get_list() {
+        if let Some(string) = items
+            .iter()
+            .map(Object::get_char)
+            .collect::<Option<String>>()
+        {
+            return Ok(Object::String(string.into()));
         }
     }
 
-    Object::Noop
+    Err(expected("list->string", "a list of characters"))
 }
 
-pub fn div(args: Vec<Object>) -> Object {
-    if let Some(a) = args[0].get_integer() {
-        if let Some(b) = args[1].get_integer() {
-            if a % b == 0 {
-                return Object::Integer(a / b);
-            } else {
-                return Object::Float(a as f64 / b as f64);
+pub fn join(args: Vec<Object>) -> EvaluationResult {
+    if let (Some(separator), Some(items)) = (args[0].get_string(), args[1].get_list()) {
+        if let Some(strings) = items
+            .iter()
+            .map(Object::get_string)
+            .collect::<Option<Vec<_>>>()
+        {
+            return Ok(Object::String(strings.join(separator).into()));
+        }
+    }
+
+    Err(expected("join", "a separator and a list of strings"))
+}
+
+fn string_function(
+    function_name: &str,
+    args: &[Object],
+    f: fn(&str) -> String,
+) -> EvaluationResult {
+    match args[0].get_string() {
+        Some(string) => Ok(Object::String(f(string).into())),
+        None => Err(expected(function_name, "a string")),
+    }
+}
+
+// Casing follows Unicode, so the result can be longer than the input, e.g.
+// `upper "ß"` is "SS" and `lower "İ"` is "i̇".
+pub fn upper(args: Vec<Object>) -> EvaluationResult {
+    string_function("upper", &args, str::to_uppercase)
+}
+
+pub fn lower(args: Vec<Object>) -> EvaluationResult {
+    string_function("lower", &args, str::to_lowercase)
+}
+
+pub fn trim(args: Vec<Object>) -> EvaluationResult {
+    string_function("trim", &args, |string| string.trim().into())
+}
+
+// An empty separator splits the string into single-character strings.
+pub fn split(args: Vec<Object>) -> EvaluationResult {
+    let (separator, string) = match (args[0].get_string(), args[1].get_string()) {
+        (Some(separator), Some(string)) => (separator, string),
+        _ => return Err(expected("split", "a separator and a string")),
+    };
+
+    let parts: Vec<Object> = if separator.is_empty() {
+        string
+            .chars()
+            .map(|character| Object::String(character.to_string().into()))
+            .collect()
+    } else {
+        string
+            .split(separator)
+            .map(|part| Object::String(part.into()))
+            .collect()
+    };
+
+    Ok(Object::List(parts.into()))
+}
+
+pub fn first(args: Vec<Object>) -> EvaluationResult {
+    match args[0].get_list().map(|items| items.first()) {
+        Some(Some(item)) => Ok(item.clone()),
+        _ => Err(expected("first", "a non-empty list")),
+    }
+}
+
+pub fn rest(args: Vec<Object>) -> EvaluationResult {
+    match args[0].get_list().map(|items| items.split_first()) {
+        Some(Some((_, rest))) => Ok(Object::List(rest.to_vec().into())),
+        _ => Err(expected("rest", "a non-empty list")),
+    }
+}
+
+pub fn cons(args: Vec<Object>) -> EvaluationResult {
+    if let Some(items) = args[1].get_list() {
+        let mut result = vec![args[0].clone()];
+        result.extend(items.iter().cloned());
+        return Ok(Object::List(result.into()));
+    }
+
+    Err(expected("cons", "a value and a list"))
+}
+
+// Like `concat`, but only accepts lists.
+pub fn append(args: Vec<Object>) -> EvaluationResult {
+    if let Some(a) = args[0].get_list() {
+        if let Some(b) = args[1].get_list() {
+            return Ok(Object::List(
+                a.iter().chain(b).cloned().collect::<Vec<_>>().into(),
+            ));
+        }
+    }
+
+    Err(expected("append", "two lists"))
+}
+
+pub fn map_new(_args: Vec<Object>) -> EvaluationResult {
+    Ok(Object::Map(HashMap::new().into()))
+}
+
+fn map_key<'a>(
+    function_name: &str,
+    map: &'a Object,
+    key: &Object,
+) -> Result<&'a HashMap<Object, Object>, RuntimeError> {
+    let entries = map
+        .get_map()
+        .ok_or_else(|| expected(function_name, "a map"))?;
+
+    if !key.is_hashable() {
+        return Err(RuntimeError::InvalidArgument(format!(
+            "`{}` can't use {} as a key",
+            function_name,
+            key.type_with_article()
+        )));
+    }
+
+    Ok(entries)
+}
+
+// Missing keys give `noop`, so that `default` can supply a fallback.
+pub fn map_get(args: Vec<Object>) -> EvaluationResult {
+    let entries = map_key("map-get", &args[0], &args[1])?;
+    Ok(entries.get(&args[1]).cloned().unwrap_or(Object::Noop))
+}
+
+// Returns a new map; the original is left as it was.
+pub fn map_set(args: Vec<Object>) -> EvaluationResult {
+    let mut entries = map_key("map-set", &args[0], &args[1])?.clone();
+    entries.insert(args[1].clone(), args[2].clone());
+    Ok(Object::Map(entries.into()))
+}
+
+pub fn map_has(args: Vec<Object>) -> EvaluationResult {
+    let entries = map_key("map-has", &args[0], &args[1])?;
+    Ok(Object::Boolean(entries.contains_key(&args[1])))
+}
+
+// The order of entries is unspecified, but `keys` and `values` agree with each
+// other for the same map.
+pub fn keys(args: Vec<Object>) -> EvaluationResult {
+    match args[0].get_map() {
+        Some(entries) => Ok(Object::List(
+            entries.keys().cloned().collect::<Vec<_>>().into(),
+        )),
+        None => Err(expected("keys", "a map")),
+    }
+}
+
+pub fn values(args: Vec<Object>) -> EvaluationResult {
+    match args[0].get_map() {
+        Some(entries) => Ok(Object::List(
+            entries.values().cloned().collect::<Vec<_>>().into(),
+        )),
+        None => Err(expected("values", "a map")),
+    }
+}
+
+pub fn substring(args: Vec<Object>) -> EvaluationResult {
+    if let Some(string) = args[0].get_string() {
+        if let Some(start) = args[1].get_integer() {
+            if let Some(end) = args[2].get_integer() {
+                let length = string.chars().count() as i64;
+
+                if start < 0 || end < start || end > length {
+                    return Err(RuntimeError::InvalidArgument(format!(
+                        "Substring {}..{} is out of range for a string of length {}",
+                        start, end, length
+                    )));
+                }
+
+                let substring: String = string
+                    .chars()
+                    .skip(start as usize)
+                    .take((end - start) as usize)
+                    .collect();
+
+                return Ok(Object::String(substring.into()));
             }
-        } else if let Some(b) = args[1].get_float() {
-            return Object::Float(a as f64 / b);
         }
-    } else if let Some(a) = args[0].get_float() {
-        if let Some(b) = args[1].get_integer() {
-            return Object::Float(a / b as f64);
-        } else if let Some(b) = args[1].get_float() {
-            return Object::Float(a / b);
+    }
+
+    Err(expected("substring", "a string and two integers"))
+}
+
+pub fn format(args: Vec<Object>) -> EvaluationResult {
+    let template = args[0]
+        .get_string()
+        .ok_or_else(|| expected("format", "a template string"))?;
+
+    let invalid = |message: &str| RuntimeError::InvalidArgument(format!("`format`: {}", message));
+
+    let mut result = String::new();
+    let mut values = args[1..].iter();
+    let mut characters = template.chars().peekable();
+
+    while let Some(character) = characters.next() {
+        match (character, characters.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                characters.next();
+                result.push(character);
+            }
+            ('{', Some('}')) => {
+                characters.next();
+
+                let value = values
+                    .next()
+                    .ok_or_else(|| invalid("too few arguments for the template"))?;
+
+                result.push_str(&value.to_string());
+            }
+            ('{', _) | ('}', _) => return Err(invalid("unmatched brace in the template")),
+            _ => result.push(character),
+        }
+    }
+
+    if values.next().is_some() {
+        return Err(invalid("too many arguments for the template"));
+    }
+
+    Ok(Object::String(result.into()))
+}
+
+pub fn error(args: Vec<Object>) -> EvaluationResult {
+    if let Some(message) = args[0].get_string() {
+        return Ok(Object::Error(message.to_string()));
+    }
+
+    Err(expected("error", "a string"))
+}
+
+// Unlike `error`, which creates a value, this fails the evaluation outright
+// until a `try` catches it.
+pub fn throw(args: Vec<Object>) -> EvaluationResult {
+    match args[0].get_string() {
+        Some(message) => Err(RuntimeError::Thrown(message.to_string())),
+        None => Err(expected("throw", "a string")),
+    }
+}
+
+// Uses the same formatting as `print`, so `noop` becomes an empty string.
+pub fn str(args: Vec<Object>) -> EvaluationResult {
+    Ok(Object::String(args[0].to_string().into()))
+}
+
+// Accepts exactly the number literals a script could contain, surrounding
+// whitespace aside.
+pub fn parse_number(args: Vec<Object>) -> EvaluationResult {
+    let string = args[0]
+        .get_string()
+        .ok_or_else(|| expected("parse-number", "a string"))?;
+
+    match Reader::new(string).tokenise().as_deref() {
+        Ok([Token::IntegerConstant(value)]) => Ok(Object::Integer(*value)),
+        Ok([Token::FloatConstant(value)]) => Ok(Object::Float(*value)),
+        _ => Err(RuntimeError::InvalidArgument(format!(
+            "`{}` is not a number",
+            string
+        ))),
+    }
+}
+
+pub fn type_of(args: Vec<Object>) -> EvaluationResult {
+    Ok(Object::String(args[0].type_name().into()))
+}
+
+// Returns the minimum number of arguments, so variadic functions give 0.
+pub fn arity(args: Vec<Object>) -> EvaluationResult {
+    match &args[0] {
+        Object::Function { argument_count, .. } => match argument_count {
+            Arity::Exact(count) | Arity::AtLeast(count) => Ok(Object::Integer(*count as i64)),
+            Arity::Variadic => Ok(Object::Integer(0)),
+        },
+        _ => Err(expected("arity", "a function")),
+    }
+}
+
+pub fn default(mut args: Vec<Object>) -> EvaluationResult {
+    let fallback = args.pop().unwrap();
+    let value = args.pop().unwrap();
+
+    match value {
+        Object::Noop => Ok(fallback),
+        value => Ok(value),
+    }
+}
+
+pub fn assert(args: Vec<Object>) -> EvaluationResult {
+    if args[0].is_truthy() {
+        Ok(Object::Noop)
+    } else {
+        Err(RuntimeError::AssertionFailed("assertion failed".into()))
+    }
+}
+
+pub fn assert_eq(args: Vec<Object>) -> EvaluationResult {
+    if args[0] == args[1] {
+        Ok(Object::Noop)
+    } else {
+        Err(RuntimeError::AssertionFailed(format!(
+            "assertion failed: {} != {}",
+            args[0], args[1]
+        )))
+    }
+}
+
+pub fn read_line(interpreter: &mut Bloodbath, _args: Vec<Object>) -> EvaluationResult {
+    match interpreter.read_line() {
+        Some(line) => Ok(Object::String(line.into())),
+        None => Ok(Object::Noop),
+    }
+}
+
+pub fn time(interpreter: &mut Bloodbath, _args: Vec<Object>) -> EvaluationResult {
+    Ok(Object::Float(interpreter.clock().time()))
+}
+
+pub fn clock(interpreter: &mut Bloodbath, _args: Vec<Object>) -> EvaluationResult {
+    Ok(Object::Float(interpreter.clock().clock()))
+}
+
+pub fn gensym(interpreter: &mut Bloodbath, _args: Vec<Object>) -> EvaluationResult {
+    Ok(Object::String(
+        format!("g#{}", interpreter.next_symbol()).into(),
+    ))
+}
+
+pub fn print(interpreter: &mut Bloodbath, args: Vec<Object>) -> EvaluationResult {
+    interpreter
+        .write_line(&args[0].to_string())
+        .map_err(|err| RuntimeError::IoFailed(err.to_string()))?;
+
+    Ok(Object::Noop)
+}
+
+pub fn read_file(args: Vec<Object>) -> EvaluationResult {
+    let path = args[0]
+        .get_string()
+        .ok_or_else(|| expected("read-file", "a path"))?;
+
+    match std::fs::read_to_string(path) {
+        Ok(contents) => Ok(Object::String(contents.into())),
+        Err(err) => Err(RuntimeError::IoFailed(format!("{}: {}", path, err))),
+    }
+}
+
+// Replaces the file's contents, creating it if needed.
+pub fn write_file(args: Vec<Object>) -> EvaluationResult {
+    let (path, contents) = path_and_contents("write-file", &args)?;
+
+    match std::fs::write(path, contents) {
+        Ok(()) => Ok(Object::Noop),
+        Err(err) => Err(RuntimeError::IoFailed(format!("{}: {}", path, err))),
+    }
+}
+
+pub fn append_file(args: Vec<Object>) -> EvaluationResult {
+    let (path, contents) = path_and_contents("append-file", &args)?;
+
+    let result = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()));
+
+    match result {
+        Ok(()) => Ok(Object::Noop),
+        Err(err) => Err(RuntimeError::IoFailed(format!("{}: {}", path, err))),
+    }
+}
+
+fn path_and_contents<'a>(
+    function_name: &str,
+    args: &'a [Object],
+) -> Result<(&'a str, &'a str), RuntimeError> {
+    match (args[0].get_string(), args[1].get_string()) {
+        (Some(path), Some(contents)) => Ok((path, contents)),
+        _ => Err(expected(function_name, "a path and a string")),
+    }
+}
+
+pub fn call(interpreter: &mut Bloodbath, args: Vec<Object>) -> EvaluationResult {
+    match (&args[0], args[1].get_list()) {
+        (
+            Object::Function {
+                argument_count,
+                implementation,
+            },
+            Some(arguments),
+        ) => {
+            if !argument_count.accepts(arguments.len()) {
+                return Err(RuntimeError::InvalidArgument(format!(
+                    "`call` got {} arguments for a function that takes {}",
+                    arguments.len(),
+                    argument_count
+                )));
+            }
+
+            implementation.call(interpreter, arguments.clone())
+        }
+        _ => Err(expected("call", "a function and a list of arguments")),
+    }
+}
+
+// Fetches a function that can be called with `count` arguments.
+fn callable<'a>(
+    function_name: &str,
+    object: &'a Object,
+    count: usize,
+) -> Result<&'a FunctionImplementation, RuntimeError> {
+    match object {
+        Object::Function {
+            argument_count,
+            implementation,
+        } if argument_count.accepts(count) => Ok(implementation),
+        _ => Err(expected(
+            function_name,
+            &format!("a function taking {} arguments", count),
+        )),
+    }
+}
+
+pub fn map(interpreter: &mut Bloodbath, args: Vec<Object>) -> EvaluationResult {
+    let function = callable("map", &args[0], 1)?;
+    let items = args[1]
+        .get_list()
+        .ok_or_else(|| expected("map", "a list"))?;
+    let mut results = Vec::new();
+
+    for item in items {
+        let result = function.call(interpreter, vec![item.clone()])?;
+
+        if result.is_error() {
+            return Ok(result);
+        }
+
+        results.push(result);
+    }
+
+    Ok(Object::List(results.into()))
+}
+
+pub fn filter(interpreter: &mut Bloodbath, args: Vec<Object>) -> EvaluationResult {
+    let predicate = callable("filter", &args[0], 1)?;
+    let items = args[1]
+        .get_list()
+        .ok_or_else(|| expected("filter", "a list"))?;
+    let mut results = Vec::new();
+
+    for item in items {
+        let keep = predicate.call(interpreter, vec![item.clone()])?;
+
+        if keep.is_error() {
+            return Ok(keep);
+        } else if keep.is_truthy() {
+            results.push(item.clone());
+        }
+    }
+
+    Ok(Object::List(results.into()))
+}
+
+pub fn reduce(interpreter: &mut Bloodbath, mut args: Vec<Object>) -> EvaluationResult {
+    let items = args.pop().unwrap();
+    let mut accumulator = args.pop().unwrap();
+
+    let function = callable("reduce", &args[0], 2)?;
+    let items = items
+        .get_list()
+        .ok_or_else(|| expected("reduce", "a list"))?;
+
+    for item in items {
+        accumulator = function.call(interpreter, vec![accumulator, item.clone()])?;
+
+        if accumulator.is_error() {
+            break;
+        }
+    }
+
+    Ok(accumulator)
+}
+
+pub fn shuffle(random: &RefCell<Random>, args: Vec<Object>) -> EvaluationResult {
+    if let Some(items) = args[0].get_list() {
+        let mut items = items.clone();
+        random.borrow_mut().shuffle(&mut items);
+        return Ok(Object::List(items.into()));
+    }
+
+    Err(expected("shuffle", "a list"))
+}
+
+pub fn rand(random: &RefCell<Random>, _args: Vec<Object>) -> EvaluationResult {
+    Ok(Object::Float(random.borrow_mut().next_float()))
+}
+
+pub fn rand_int(random: &RefCell<Random>, args: Vec<Object>) -> EvaluationResult {
+    let (low, high) = integer_pair("rand-int", &args)?;
+
+    if low > high {
+        return Err(RuntimeError::InvalidArgument(format!(
+            "Cannot pick a number between {} and {}",
+            low, high
+        )));
+    }
+
+    Ok(Object::Integer(
+        random.borrow_mut().next_in_range(low, high),
+    ))
+}
+
+pub fn sample(random: &RefCell<Random>, args: Vec<Object>) -> EvaluationResult {
+    if let Some(count) = args[0].get_integer() {
+        if let Some(items) = args[1].get_list() {
+            if count < 0 || count as usize > items.len() {
+                return Err(RuntimeError::InvalidArgument(format!(
+                    "Cannot sample {} elements from a list of length {}",
+                    count,
+                    items.len()
+                )));
+            }
+
+            let mut items = items.clone();
+            random.borrow_mut().shuffle(&mut items);
+            items.truncate(count as usize);
+
+            return Ok(Object::List(items.into()));
         }
     }
 
-    Object::Noop
+    Err(expected("sample", "an integer and a list"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    fn pair(a: Object, b: Object) -> Vec<Object> {
+        vec![a, b]
+    }
+
+    fn floats(a: f64, b: f64) -> Vec<Object> {
+        pair(Object::Float(a), Object::Float(b))
+    }
+
+    fn integers(a: i64, b: i64) -> Vec<Object> {
+        pair(Object::Integer(a), Object::Integer(b))
+    }
+
+    fn string(value: &str) -> Object {
+        Object::String(value.into())
+    }
+
+    fn strings(items: &[&str]) -> Object {
+        Object::List(
+            items
+                .iter()
+                .map(|item| string(item))
+                .collect::<Vec<_>>()
+                .into(),
+        )
+    }
+
+    fn integer_list(items: &[i64]) -> Object {
+        Object::List(
+            items
+                .iter()
+                .copied()
+                .map(Object::Integer)
+                .collect::<Vec<_>>()
+                .into(),
+        )
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let int = Object::Integer;
+        let float = Object::Float;
+
+        assert_eq!(add(pair(int(1), int(2))), Ok(int(3)));
+        assert_eq!(add(pair(int(1), float(0.5))), Ok(float(1.5)));
+        assert_eq!(add(pair(float(0.5), int(1))), Ok(float(1.5)));
+        assert_eq!(add(pair(float(0.5), float(0.25))), Ok(float(0.75)));
+        assert_eq!(add(vec![]), Ok(int(0)));
+
+        assert_eq!(sub(pair(int(1), int(2))), Ok(int(-1)));
+        assert_eq!(sub(pair(int(1), float(0.5))), Ok(float(0.5)));
+        assert_eq!(sub(pair(float(0.5), int(1))), Ok(float(-0.5)));
+        assert_eq!(sub(pair(float(0.5), float(0.25))), Ok(float(0.25)));
+
+        assert_eq!(mul(pair(int(3), int(2))), Ok(int(6)));
+        assert_eq!(mul(pair(int(3), float(0.5))), Ok(float(1.5)));
+        assert_eq!(mul(pair(float(0.5), int(3))), Ok(float(1.5)));
+        assert_eq!(mul(pair(float(0.5), float(0.5))), Ok(float(0.25)));
+        assert_eq!(mul(vec![]), Ok(int(1)));
+
+        assert_eq!(div(pair(int(6), int(2))), Ok(float(3.0)));
+        assert_eq!(div(pair(int(1), int(2))), Ok(float(0.5)));
+        assert_eq!(div(pair(int(1), float(0.5))), Ok(float(2.0)));
+        assert_eq!(div(pair(float(1.0), int(2))), Ok(float(0.5)));
+        assert_eq!(div(pair(float(1.0), float(0.5))), Ok(float(2.0)));
+
+        assert_eq!(div(pair(int(4), int(2))), Ok(float(2.0)));
+        assert_eq!(div(pair(int(5), int(2))), Ok(float(2.5)));
+
+        assert_eq!(floor_div(pair(int(6), int(2))), Ok(int(3)));
+        assert_eq!(floor_div(pair(int(-1), int(2))), Ok(int(-1)));
+        assert!(floor_div(pair(float(1.0), int(2))).is_err());
+        assert!(div(pair(int(4), int(0))).is_err());
+        assert_eq!(div(pair(int(4), float(0.0))), Ok(float(f64::INFINITY)));
+
+        assert_eq!(neg(vec![int(1)]), Ok(int(-1)));
+        assert_eq!(neg(vec![float(0.5)]), Ok(float(-0.5)));
+        assert_eq!(neg(vec![int(i64::MAX)]), Ok(int(-i64::MAX)));
+
+        assert_eq!(
+            add(pair(int(i64::MAX), int(1))),
+            Err(RuntimeError::InvalidArgument(
+                "`+`: integer overflow".into()
+            ))
+        );
+        assert_eq!(
+            add(vec![int(i64::MAX), int(1), int(-1)]),
+            Err(RuntimeError::InvalidArgument(
+                "`+`: integer overflow".into()
+            ))
+        );
+        assert_eq!(
+            mul(pair(int(i64::MAX), int(2))),
+            Err(RuntimeError::InvalidArgument(
+                "`*`: integer overflow".into()
+            ))
+        );
+        assert_eq!(
+            sub(pair(int(i64::MIN), int(1))),
+            Err(RuntimeError::InvalidArgument(
+                "`-`: integer overflow".into()
+            ))
+        );
+        assert_eq!(
+            add(pair(int(i64::MAX), float(1.0))),
+            Ok(float(i64::MAX as f64 + 1.0))
+        );
+        assert_eq!(
+            neg(vec![int(i64::MIN)]),
+            Err(RuntimeError::InvalidArgument(
+                "`neg`: integer overflow".into()
+            ))
+        );
+
+        assert_eq!(
+            add(pair(int(1), Object::Noop)),
+            Err(RuntimeError::InvalidArgument("`+` expects numbers".into()))
+        );
+
+        assert_eq!(
+            div(pair(Object::Noop, int(1))),
+            Err(RuntimeError::InvalidArgument(
+                "`/` expects two numbers".into()
+            ))
+        );
+
+        assert!(neg(vec![Object::Noop]).is_err());
+    }
+
+    #[test]
+    fn test_round() {
+        assert_eq!(round(vec![Object::Float(2.5)]), Ok(Object::Float(2.0)));
+        assert_eq!(round(vec![Object::Float(3.5)]), Ok(Object::Float(4.0)));
+        assert_eq!(round(vec![Object::Float(2.4)]), Ok(Object::Float(2.0)));
+        assert_eq!(round(vec![Object::Float(-2.6)]), Ok(Object::Float(-3.0)));
+        assert_eq!(round(vec![Object::Integer(7)]), Ok(Object::Integer(7)));
+
+        assert_eq!(
+            round(vec![Object::Float(1.23456), Object::Integer(2)]),
+            Ok(Object::Float(1.23))
+        );
+
+        assert_eq!(
+            round(vec![Object::Float(f64::MAX), Object::Integer(10)]),
+            Ok(Object::Float(f64::MAX))
+        );
+
+        assert!(round(vec![Object::Float(1.0), Object::Integer(-1)]).is_err());
+        assert!(round(vec![Object::Float(1.0), Object::Float(1.0)]).is_err());
+        assert!(round(vec![Object::Noop]).is_err());
+        assert!(round(vec![Object::Float(1.0), Object::Integer(1), Object::Noop]).is_err());
+    }
+
+    #[test]
+    fn test_trigonometry() {
+        assert_eq!(sin(vec![Object::Integer(0)]), Ok(Object::Float(0.0)));
+        assert_eq!(cos(vec![Object::Integer(0)]), Ok(Object::Float(1.0)));
+        assert_eq!(tan(vec![Object::Float(0.0)]), Ok(Object::Float(0.0)));
+
+        let half_pi = Object::Float(std::f64::consts::FRAC_PI_2);
+        let value = sin(vec![half_pi]).unwrap().get_float().unwrap();
+        assert!((value - 1.0).abs() < 1e-12);
+
+        assert!(cos(vec![Object::Noop]).is_err());
+    }
+
+    #[test]
+    fn test_log_exp() {
+        let float = |value: EvaluationResult| value.unwrap().get_float().unwrap();
+
+        assert_eq!(exp(vec![Object::Integer(0)]), Ok(Object::Float(1.0)));
+        assert_eq!(log(vec![Object::Integer(1)]), Ok(Object::Float(0.0)));
+
+        let e = Object::Float(std::f64::consts::E);
+        assert!((float(log(vec![e])) - 1.0).abs() < 1e-12);
+
+        let value = float(log(vec![Object::Integer(8), Object::Integer(2)]));
+        assert!((value - 3.0).abs() < 1e-12);
+
+        assert!(log(vec![Object::Integer(0)]).is_err());
+        assert!(log(vec![Object::Float(-1.0)]).is_err());
+        assert!(log(vec![Object::Float(f64::NAN)]).is_err());
+        assert!(log(vec![Object::Integer(8), Object::Integer(1)]).is_err());
+        assert!(log(vec![Object::Integer(8), Object::Integer(-2)]).is_err());
+        assert!(exp(vec![Object::Noop]).is_err());
+    }
+
+    #[test]
+    fn test_min_max() {
+        assert_eq!(
+            min(pair(Object::Integer(1), Object::Integer(2))),
+            Ok(Object::Integer(1))
+        );
+
+        assert_eq!(
+            max(pair(Object::Integer(1), Object::Integer(2))),
+            Ok(Object::Integer(2))
+        );
+
+        assert_eq!(
+            min(pair(Object::Float(1.5), Object::Float(-2.5))),
+            Ok(Object::Float(-2.5))
+        );
+
+        assert_eq!(
+            max(pair(Object::Float(1.5), Object::Float(-2.5))),
+            Ok(Object::Float(1.5))
+        );
+
+        assert_eq!(
+            max(pair(Object::Integer(1), Object::Float(2.5))),
+            Ok(Object::Float(2.5))
+        );
+
+        assert_eq!(
+            min(pair(Object::Integer(3), Object::Float(2.5))),
+            Ok(Object::Float(2.5))
+        );
+
+        assert_eq!(
+            max(pair(Object::Integer(3), Object::Float(2.5))),
+            Ok(Object::Float(3.0))
+        );
+
+        assert_eq!(
+            min(pair(Object::Float(0.5), Object::Integer(1))),
+            Ok(Object::Float(0.5))
+        );
+
+        assert!(min(pair(Object::Noop, Object::Integer(1))).is_err());
+    }
+
+    #[test]
+    fn test_special_floats() {
+        assert_eq!(equal(floats(f64::NAN, f64::NAN)), Ok(Object::Boolean(true)));
+        assert_eq!(equal(floats(f64::NAN, 1.0)), Ok(Object::Boolean(false)));
+        assert_eq!(less(floats(f64::NAN, f64::NAN)), Ok(Object::Boolean(false)));
+        assert_eq!(
+            less_or_equal(floats(f64::NAN, f64::NAN)),
+            Ok(Object::Boolean(false))
+        );
+
+        assert_eq!(
+            equal(floats(f64::INFINITY, f64::INFINITY)),
+            Ok(Object::Boolean(true))
+        );
+
+        assert_eq!(eq(floats(f64::NAN, f64::NAN)), Ok(Object::Boolean(true)));
+        assert_eq!(eq(floats(f64::NAN, 1.0)), Ok(Object::Boolean(false)));
+        assert_eq!(ne(floats(f64::NAN, f64::NAN)), Ok(Object::Boolean(false)));
+
+        assert_eq!(
+            eq(vec![
+                Object::List(vec![Object::Float(f64::NAN)].into()),
+                Object::List(vec![Object::Float(f64::NAN)].into()),
+            ]),
+            Ok(Object::Boolean(true))
+        );
+
+        assert_eq!(min(floats(f64::NAN, 1.0)), Ok(Object::Float(f64::NAN)));
+        assert_eq!(min(floats(1.0, f64::NAN)), Ok(Object::Float(f64::NAN)));
+        assert_eq!(max(floats(f64::NAN, 1.0)), Ok(Object::Float(f64::NAN)));
+        assert_eq!(max(floats(1.0, f64::NAN)), Ok(Object::Float(f64::NAN)));
+        assert_eq!(
+            max(vec![Object::Integer(1), Object::Float(f64::NAN)]),
+            Ok(Object::Float(f64::NAN))
+        );
+
+        assert_eq!(
+            is_nan(vec![Object::Float(f64::NAN)]),
+            Ok(Object::Boolean(true))
+        );
+        assert_eq!(is_nan(vec![Object::Integer(1)]), Ok(Object::Boolean(false)));
+        assert!(is_nan(vec![Object::Noop]).is_err());
+
+        assert_eq!(
+            is_infinite(vec![Object::Float(f64::NEG_INFINITY)]),
+            Ok(Object::Boolean(true))
+        );
+
+        assert_eq!(
+            is_infinite(vec![Object::Float(1.0)]),
+            Ok(Object::Boolean(false))
+        );
+        assert!(is_infinite(vec![Object::String("inf".into())]).is_err());
+    }
+
+    #[test]
+    fn test_eq() {
+        assert_eq!(
+            eq(pair(Object::Integer(1), Object::Integer(1))),
+            Ok(Object::Boolean(true))
+        );
+
+        assert_eq!(
+            eq(pair(Object::Integer(1), Object::Float(1.0))),
+            Ok(Object::Boolean(false))
+        );
+
+        assert_eq!(
+            eq(pair(Object::Float(0.5), Object::Float(0.5))),
+            Ok(Object::Boolean(true))
+        );
+
+        assert_eq!(
+            eq(pair(Object::String("a".into()), Object::String("a".into()))),
+            Ok(Object::Boolean(true))
+        );
+
+        assert_eq!(
+            eq(pair(Object::Noop, Object::Noop)),
+            Ok(Object::Boolean(true))
+        );
+
+        assert_eq!(
+            ne(pair(Object::Noop, Object::Boolean(false))),
+            Ok(Object::Boolean(true))
+        );
+
+        assert_eq!(
+            ne(pair(Object::String("a".into()), Object::String("a".into()))),
+            Ok(Object::Boolean(false))
+        );
+    }
+
+    #[test]
+    fn test_bitwise() {
+        assert_eq!(band(integers(12, 10)), Ok(Object::Integer(8)));
+        assert_eq!(bor(integers(12, 10)), Ok(Object::Integer(14)));
+        assert_eq!(bxor(integers(12, 10)), Ok(Object::Integer(6)));
+        assert_eq!(shl(integers(1, 4)), Ok(Object::Integer(16)));
+        assert_eq!(shr(integers(-16, 2)), Ok(Object::Integer(-4)));
+
+        assert_eq!(
+            band(vec![Object::Float(1.0), Object::Integer(1)]),
+            Err(RuntimeError::InvalidArgument(
+                "`band` expects two integers".into()
+            ))
+        );
+
+        assert_eq!(
+            shl(integers(1, -1)),
+            Err(RuntimeError::InvalidArgument(
+                "`shl` cannot shift by -1 bits".into()
+            ))
+        );
+
+        assert!(shr(integers(1, 64)).is_err());
+    }
+
+    #[test]
+    fn test_default() {
+        assert_eq!(
+            default(pair(Object::Integer(5), Object::Integer(0))),
+            Ok(Object::Integer(5))
+        );
+
+        assert_eq!(
+            default(pair(Object::Noop, Object::Integer(0))),
+            Ok(Object::Integer(0))
+        );
+
+        assert_eq!(
+            default(pair(Object::Boolean(false), Object::Integer(0))),
+            Ok(Object::Boolean(false))
+        );
+    }
+
+    #[test]
+    fn test_assert() {
+        assert_eq!(assert(vec![Object::Boolean(true)]), Ok(Object::Noop));
+        assert_eq!(assert(vec![Object::Integer(0)]), Ok(Object::Noop));
+
+        assert_eq!(
+            assert(vec![Object::Boolean(false)]),
+            Err(RuntimeError::AssertionFailed("assertion failed".into()))
+        );
+
+        assert_eq!(
+            assert_eq(vec![Object::Integer(2), Object::Integer(2)]),
+            Ok(Object::Noop)
+        );
+
+        assert_eq!(
+            assert_eq(vec![Object::Integer(2), Object::Integer(3)]),
+            Err(RuntimeError::AssertionFailed(
+                "assertion failed: 2 != 3".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_list_primitives() {
+        assert_eq!(
+            cons(vec![Object::Integer(0), integer_list(&[1, 2])]),
+            Ok(integer_list(&[0, 1, 2]))
+        );
+        assert_eq!(
+            cons(vec![Object::Integer(0), integer_list(&[])]),
+            Ok(integer_list(&[0]))
+        );
+        assert!(cons(vec![Object::Integer(0), Object::Integer(1)]).is_err());
+
+        assert_eq!(first(vec![integer_list(&[1, 2])]), Ok(Object::Integer(1)));
+        assert_eq!(rest(vec![integer_list(&[1, 2])]), Ok(integer_list(&[2])));
+        assert_eq!(rest(vec![integer_list(&[1])]), Ok(integer_list(&[])));
+
+        assert!(first(vec![integer_list(&[])]).is_err());
+        assert!(rest(vec![integer_list(&[])]).is_err());
+        assert!(first(vec![Object::String("ab".into())]).is_err());
+
+        assert_eq!(
+            append(vec![integer_list(&[1, 2]), integer_list(&[3, 4])]),
+            Ok(integer_list(&[1, 2, 3, 4]))
+        );
+        assert_eq!(
+            append(vec![integer_list(&[]), integer_list(&[1])]),
+            Ok(integer_list(&[1]))
+        );
+        assert_eq!(
+            append(vec![integer_list(&[1]), integer_list(&[])]),
+            Ok(integer_list(&[1]))
+        );
+        assert_eq!(
+            append(vec![integer_list(&[]), integer_list(&[])]),
+            Ok(integer_list(&[]))
+        );
+
+        assert!(append(vec![integer_list(&[1]), Object::Integer(2)]).is_err());
+        assert!(append(vec![Object::String("a".into()), Object::String("b".into())]).is_err());
+    }
+
+    #[test]
+    fn test_maps() {
+        let empty = map_new(vec![]).unwrap();
+        let one = Object::String("one".into());
+
+        let map = map_set(vec![empty.clone(), Object::Integer(1), one.clone()]).unwrap();
+        let map = map_set(vec![map, one.clone(), Object::Integer(1)]).unwrap();
+
+        assert_eq!(
+            map_get(vec![map.clone(), Object::Integer(1)]),
+            Ok(one.clone())
+        );
+        assert_eq!(
+            map_get(vec![map.clone(), one.clone()]),
+            Ok(Object::Integer(1))
+        );
+        assert_eq!(
+            map_get(vec![map.clone(), Object::Integer(2)]),
+            Ok(Object::Noop)
+        );
+
+        assert_eq!(
+            map_has(vec![map.clone(), Object::Integer(1)]),
+            Ok(Object::Boolean(true))
+        );
+        assert_eq!(
+            map_has(vec![empty.clone(), Object::Integer(1)]),
+            Ok(Object::Boolean(false))
+        );
+
+        let map = map_set(vec![map, Object::Integer(1), Object::Integer(2)]).unwrap();
+        assert_eq!(
+            map_get(vec![map.clone(), Object::Integer(1)]),
+            Ok(Object::Integer(2))
+        );
+
+        assert!(map_get(vec![map.clone(), Object::Float(1.0)]).is_err());
+        assert!(map_set(vec![map.clone(), Object::List(vec![].into()), Object::Noop]).is_err());
+        assert!(map_has(vec![Object::List(vec![].into()), Object::Integer(1)]).is_err());
+
+        let map_keys = keys(vec![map.clone()]).unwrap();
+        let map_values = values(vec![map.clone()]).unwrap();
+        let map_keys = map_keys.get_list().unwrap();
+        let map_values = map_values.get_list().unwrap();
+
+        assert_eq!(map_keys.len(), 2);
+        assert_eq!(map_values.len(), 2);
+
+        for (key, value) in map_keys.iter().zip(map_values) {
+            assert_eq!(map_get(vec![map.clone(), key.clone()]), Ok(value.clone()));
+        }
+
+        assert!(map_keys.contains(&Object::Integer(1)));
+        assert!(map_keys.contains(&one));
+        assert_eq!(keys(vec![empty.clone()]), Ok(Object::List(vec![].into())));
+        assert!(values(vec![Object::List(vec![].into())]).is_err());
+    }
+
+    #[test]
+    fn test_str() {
+        assert_eq!(str(vec![Object::Noop]), Ok(string("")));
+        assert_eq!(str(vec![Object::Boolean(true)]), Ok(string("true")));
+        assert_eq!(str(vec![Object::Integer(-12)]), Ok(string("-12")));
+        assert_eq!(str(vec![Object::Float(2.5)]), Ok(string("2.5")));
+        assert_eq!(str(vec![Object::String("hi".into())]), Ok(string("hi")));
+        assert_eq!(str(vec![Object::Char('c')]), Ok(string("c")));
+        assert_eq!(
+            str(vec![Object::Error("bad".into())]),
+            Ok(string("error: bad"))
+        );
+
+        assert_eq!(
+            str(vec![Object::List(
+                vec![Object::Integer(1), Object::String("x".into())].into()
+            )]),
+            Ok(string("[1 x]"))
+        );
+
+        assert_eq!(
+            str(vec![Object::Map(
+                HashMap::from([(Object::Integer(1), Object::Integer(2))]).into()
+            )]),
+            Ok(string("{1: 2}"))
+        );
+
+        let function = Object::Function {
+            argument_count: Arity::Exact(2),
+            implementation: FunctionImplementation::Builtin(Rc::new(sub)),
+        };
+        assert_eq!(str(vec![function]), Ok(string("<function/2>")));
+    }
+
+    #[test]
+    fn test_parse_number() {
+        let parse = |input: &str| parse_number(vec![Object::String(input.into())]);
+
+        assert_eq!(parse("42"), Ok(Object::Integer(42)));
+        assert_eq!(parse(" -7 "), Ok(Object::Integer(-7)));
+        assert_eq!(parse("2.5"), Ok(Object::Float(2.5)));
+        assert_eq!(parse(".5"), Ok(Object::Float(0.5)));
+        assert_eq!(parse("1_000"), Ok(Object::Integer(1000)));
+
+        assert!(parse("").is_err());
+        assert!(parse("abc").is_err());
+        assert!(parse("12x").is_err());
+        assert!(parse("1 2").is_err());
+        assert!(parse("99999999999999999999").is_err());
+        assert!(parse_number(vec![Object::Integer(1)]).is_err());
+    }
+
+    #[test]
+    fn test_read_file() {
+        let path = std::env::temp_dir().join(format!("bloodbath-test-{}.txt", std::process::id()));
+        std::fs::write(&path, "héllo\nworld").unwrap();
+
+        let path_string = Object::String(path.to_string_lossy().into());
+        let contents = read_file(vec![path_string.clone()]);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(contents, Ok(Object::String("héllo\nworld".into())));
+
+        assert!(matches!(
+            read_file(vec![path_string]),
+            Err(RuntimeError::IoFailed(_))
+        ));
+        assert!(read_file(vec![Object::Integer(1)]).is_err());
+    }
+
+    #[test]
+    fn test_write_file() {
+        let path =
+            std::env::temp_dir().join(format!("bloodbath-write-test-{}.txt", std::process::id()));
+        let path_string = Object::String(path.to_string_lossy().into());
+
+        assert_eq!(
+            write_file(vec![path_string.clone(), string("first")]),
+            Ok(Object::Noop)
+        );
+        assert_eq!(
+            write_file(vec![path_string.clone(), string("second")]),
+            Ok(Object::Noop)
+        );
+        assert_eq!(
+            append_file(vec![path_string.clone(), string(" and third")]),
+            Ok(Object::Noop)
+        );
+
+        let contents = read_file(vec![path_string.clone()]);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(contents, Ok(string("second and third")));
+
+        assert!(write_file(vec![path_string.clone(), Object::Integer(1)]).is_err());
+
+        let directory = Object::String(std::env::temp_dir().to_string_lossy().into());
+        assert!(matches!(
+            write_file(vec![directory, string("x")]),
+            Err(RuntimeError::IoFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_char_at() {
+        let args =
+            |string: &str, index: i64| vec![Object::String(string.into()), Object::Integer(index)];
+
+        assert_eq!(char_at(args("abc", 0)), Ok(Object::Char('a')));
+        assert_eq!(char_at(args("héllo", 1)), Ok(Object::Char('é')));
+
+        assert!(char_at(args("abc", 3)).is_err());
+        assert!(char_at(args("abc", -1)).is_err());
+        assert!(char_at(args("", 0)).is_err());
+        assert!(char_at(vec![Object::Char('a'), Object::Integer(0)]).is_err());
+    }
+
+    #[test]
+    fn test_string_list_conversion() {
+        let list = string_to_list(vec![Object::String("hello".into())]).unwrap();
+
+        assert_eq!(
+            list,
+            Object::List("hello".chars().map(Object::Char).collect::<Vec<_>>().into())
+        );
+
+        assert_eq!(
+            list_to_string(vec![list]),
+            Ok(Object::String("hello".into()))
+        );
+
+        assert_eq!(
+            string_to_list(vec![Object::String("".into())]),
+            Ok(Object::List(vec![].into()))
+        );
+
+        assert_eq!(
+            list_to_string(vec![Object::List(
+                vec![Object::Char('a'), Object::Integer(1)].into()
+            )]),
+            Err(RuntimeError::InvalidArgument(
+                "`list->string` expects a list of characters".into()
+            ))
+        );
+
+        assert!(string_to_list(vec![Object::Char('a')]).is_err());
+    }
+
+    #[test]
+    fn test_join() {
+        assert_eq!(
+            join(vec![string(", "), strings(&["a", "b", "c"])]),
+            Ok(string("a, b, c"))
+        );
+
+        assert_eq!(join(vec![string(", "), strings(&["a"])]), Ok(string("a")));
+        assert_eq!(join(vec![string(", "), strings(&[])]), Ok(string("")));
+        assert_eq!(
+            join(vec![string(""), strings(&["a", "b"])]),
+            Ok(string("ab"))
+        );
+
+        assert_eq!(
+            join(vec![
+                string(", "),
+                Object::List(vec![string("a"), Object::Integer(1)].into())
+            ]),
+            Err(RuntimeError::InvalidArgument(
+                "`join` expects a separator and a list of strings".into()
+            ))
+        );
+
+        assert!(join(vec![Object::Char(','), strings(&["a"])]).is_err());
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!(
+            split(vec![string(","), string("a,b,c")]),
+            Ok(strings(&["a", "b", "c"]))
+        );
+
+        assert_eq!(
+            split(vec![string(", "), string("a, b, ")]),
+            Ok(strings(&["a", "b", ""]))
+        );
+
+        assert_eq!(
+            split(vec![string(""), string("héy")]),
+            Ok(strings(&["h", "é", "y"]))
+        );
+
+        assert_eq!(split(vec![string(","), string("")]), Ok(strings(&[""])));
+        assert_eq!(split(vec![string(""), string("")]), Ok(strings(&[])));
+
+        assert_eq!(
+            join(vec![
+                string(","),
+                split(vec![string(","), string("a,,b")]).unwrap()
+            ]),
+            Ok(string("a,,b"))
+        );
+
+        assert!(split(vec![Object::Char(','), string("a,b")]).is_err());
+        assert!(split(vec![string(","), strings(&["a"])]).is_err());
+    }
+
+    #[test]
+    fn test_string_case_and_trim() {
+        let argument = |value: &str| vec![string(value)];
+        let ok = |value: &str| Ok(string(value));
+
+        assert_eq!(upper(argument("abc")), ok("ABC"));
+        assert_eq!(upper(argument("héllo")), ok("HÉLLO"));
+        assert_eq!(upper(argument("straße")), ok("STRASSE"));
+
+        assert_eq!(lower(argument("ABC")), ok("abc"));
+        assert_eq!(lower(argument("ÀB1")), ok("àb1"));
+        assert_eq!(lower(argument("İ")), ok("i\u{307}"));
+
+        assert_eq!(trim(argument("  x  ")), ok("x"));
+        assert_eq!(trim(argument("\t a b\n")), ok("a b"));
+        assert_eq!(trim(argument("   ")), ok(""));
+
+        assert_eq!(
+            upper(vec![Object::Char('a')]),
+            Err(RuntimeError::InvalidArgument(
+                "`upper` expects a string".into()
+            ))
+        );
+        assert!(lower(vec![Object::Integer(1)]).is_err());
+        assert!(trim(vec![Object::Noop]).is_err());
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            format(vec![
+                string("x = {} y = {}"),
+                Object::Integer(1),
+                string("two"),
+            ]),
+            Ok(string("x = 1 y = two"))
+        );
+
+        assert_eq!(
+            format(vec![string("{{}} {{{}}}"), Object::Float(0.5)]),
+            Ok(string("{} {0.5}"))
+        );
+
+        assert_eq!(format(vec![string("plain")]), Ok(string("plain")));
+
+        assert_eq!(
+            format(vec![string("{} {}"), Object::Integer(1)]),
+            Err(RuntimeError::InvalidArgument(
+                "`format`: too few arguments for the template".into()
+            ))
+        );
+
+        assert_eq!(
+            format(vec![string("{}"), Object::Integer(1), Object::Integer(2)]),
+            Err(RuntimeError::InvalidArgument(
+                "`format`: too many arguments for the template".into()
+            ))
+        );
+
+        assert!(format(vec![string("{ }")]).is_err());
+        assert!(format(vec![string("}")]).is_err());
+        assert!(format(vec![Object::Integer(1)]).is_err());
+    }
 }
@@ -0,0 +1,63 @@
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+// A source of time for the `time` and `clock` builtins. Embedders can supply
+// their own, e.g. to make scripts deterministic in tests.
+pub trait Clock {
+    // Seconds since the Unix epoch.
+    fn time(&self) -> f64;
+
+    // Seconds since some fixed point in the past. Never goes backwards.
+    fn clock(&self) -> f64;
+}
+
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn time(&self) -> f64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs_f64())
+            .unwrap_or(0.0)
+    }
+
+    fn clock(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_system_clock() {
+        let clock = SystemClock::new();
+
+        let first = clock.clock();
+        let second = clock.clock();
+
+        assert!(first >= 0.0);
+        assert!(second >= first);
+
+        // Sometime after 2020.
+        assert!(clock.time() > 1_577_836_800.0);
+    }
+}
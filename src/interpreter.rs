@@ -1,367 +1,4169 @@
+use crate::clock::Clock;
+use crate::clock::SystemClock;
+use crate::object::Arity;
 use crate::object::FunctionImplementation;
 use crate::object::Object;
+use crate::random::Random;
 use crate::reader::Reader;
 use crate::reader::ReaderError;
 use crate::reader::Token;
+use crate::reader::TokenStream;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::io::BufRead;
+use std::io::Read;
+use std::io::Write;
 use std::rc::Rc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
 
+#[derive(Debug)]
 pub enum Expression {
     Constant(Object),
     Variable(String),
     Compound(Vec<Expression>),
+    List(Vec<Expression>),
     Set(String, Box<Expression>),
+    Update(String, Box<Expression>),
     FunctionCall(FunctionImplementation, Vec<Expression>),
     If(Box<Expression>, Box<Expression>, Option<Box<Expression>>),
+    While(Box<Expression>, Box<Expression>),
+    Repeat(Box<Expression>, Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Cond(Vec<(Expression, Expression)>, Option<Box<Expression>>),
+    Let(String, Box<Expression>, Box<Expression>),
+    ForEach(String, Box<Expression>, Box<Expression>),
+    Try(Box<Expression>, Box<Expression>),
+    Defined(String),
 }
 
 impl Expression {
-    pub fn evaluate(&self, interpreter: &mut Bloodbath) -> Object {
-        match self {
-            Self::Constant(result) => result.clone(),
-            Self::Variable(name) => interpreter.variable_get(&name),
-            Self::Compound(expressions) => {
-                let mut result = Object::Noop;
+    pub fn evaluate(&self, interpreter: &mut Bloodbath) -> EvaluationResult {
+        interpreter.check_cancelled()?;
+        interpreter.count_step()?;
 
-                for expression in expressions {
-                    result = expression.evaluate(interpreter);
-                }
+        let result = self.evaluate_untraced(interpreter);
+
+        if interpreter.trace {
+            interpreter.write_trace(self, &result);
+        }
 
-                result
+        result
+    }
+
+    // Every arm with locals of its own lives in a separate method. In debug
+    // builds a function's frame holds the locals of all of its arms, and this
+    // one is on the stack once per level of script recursion.
+    fn evaluate_untraced(&self, interpreter: &mut Bloodbath) -> EvaluationResult {
+        match self {
+            Self::Constant(result) => Ok(result.clone()),
+            Self::Variable(name) => match interpreter.variable_get(name) {
+                Some(value) => Ok(Object::clone(&value)),
+                None => Err(RuntimeError::UndefinedVariable(name.clone())),
+            },
+            Self::Compound(expressions) => Self::evaluate_compound(expressions, interpreter),
+            Self::List(expressions) => Self::evaluate_list(expressions, interpreter),
+            Self::Update(name, _) if !interpreter.is_defined(name) => {
+                Err(RuntimeError::UndefinedVariable(name.clone()))
             }
-            Self::Set(name, value) => {
-                let value = value.evaluate(interpreter);
-                interpreter.variable_set(&name, value.clone());
-                value
+            Self::Set(name, value) | Self::Update(name, value) => {
+                Self::evaluate_set(name, value, interpreter)
             }
             Self::FunctionCall(implementation, args) => {
-                let args = args.iter().map(|x| x.evaluate(interpreter)).collect();
-                implementation.call(args)
+                Self::evaluate_call(implementation, args, interpreter)
             }
-            Self::If(condition, if_true, otherwise) => match condition.evaluate(interpreter) {
-                Object::Noop => match otherwise {
-                    Some(otherwise) => otherwise.evaluate(interpreter),
-                    None => Object::Noop,
-                },
-                _ => if_true.evaluate(interpreter),
-            },
+            Self::If(condition, if_true, otherwise) => {
+                Self::evaluate_if(condition, if_true, otherwise.as_deref(), interpreter)
+            }
+            Self::Cond(branches, otherwise) => {
+                Self::evaluate_cond(branches, otherwise.as_deref(), interpreter)
+            }
+            Self::And(left, right) => Self::evaluate_and(left, right, interpreter),
+            Self::Or(left, right) => Self::evaluate_or(left, right, interpreter),
+            Self::While(condition, body) => Self::evaluate_while(condition, body, interpreter),
+            Self::Repeat(count, body) => Self::evaluate_repeat(count, body, interpreter),
+            Self::Let(name, value, body) => Self::evaluate_let(name, value, body, interpreter),
+            Self::ForEach(name, items, body) => {
+                Self::evaluate_for_each(name, items, body, interpreter)
+            }
+            Self::Defined(name) => Ok(Object::Boolean(interpreter.is_defined(name))),
+            Self::Try(body, handler) => Self::evaluate_try(body, handler, interpreter),
         }
     }
-}
 
-#[derive(Debug, PartialEq)]
-pub enum ParserError {
-    ReadingFailed(ReaderError),
-    ExpectedAnExpression(String),
-    ExpectedAnIdentifier(String),
-    UnterminatedCompoundExpression,
-    UnexpectedBrace,
-}
+    fn evaluate_compound(
+        expressions: &[Expression],
+        interpreter: &mut Bloodbath,
+    ) -> EvaluationResult {
+        let mut result = Object::Noop;
 
-pub struct Bloodbath {
-    environment: HashMap<String, Object>,
-}
+        for expression in expressions {
+            result = expression.evaluate(interpreter)?;
 
-type ParserResult = Result<Expression, ParserError>;
+            if result.is_error() {
+                break;
+            }
+        }
 
-impl Bloodbath {
-    pub fn new() -> Self {
-        let mut us = Self {
-            environment: HashMap::new(),
-        };
+        Ok(result)
+    }
 
-        us.register(&"+".into(), 2, crate::builtins::add);
-        us.register(&"-".into(), 2, crate::builtins::sub);
-        us.register(&"*".into(), 2, crate::builtins::mul);
-        us.register(&"/".into(), 2, crate::builtins::div);
+    fn evaluate_list(expressions: &[Expression], interpreter: &mut Bloodbath) -> EvaluationResult {
+        let items: Vec<Object> = expressions
+            .iter()
+            .map(|x| x.evaluate(interpreter))
+            .collect::<Result<_, _>>()?;
 
-        us
+        match items.iter().find(|x| x.is_error()) {
+            Some(error) => Ok(error.clone()),
+            None => Ok(Object::List(items.into())),
+        }
     }
 
-    pub fn variable_get(&mut self, variable_name: &String) -> Object {
-        match self.environment.get(variable_name) {
-            Some(value) => value.clone(),
-            None => {
-                self.variable_set(variable_name, Object::Noop);
-                Object::Noop
-            }
+    fn evaluate_set(
+        name: &str,
+        value: &Expression,
+        interpreter: &mut Bloodbath,
+    ) -> EvaluationResult {
+        let value = value.evaluate(interpreter)?;
+
+        if !value.is_error() {
+            interpreter.variable_set(name, value.clone());
         }
+
+        Ok(value)
     }
 
-    pub fn variable_set(&mut self, variable_name: &String, new_value: Object) {
-        self.environment
-            .insert(variable_name.clone(), new_value.clone());
+    fn evaluate_call(
+        implementation: &FunctionImplementation,
+        args: &[Expression],
+        interpreter: &mut Bloodbath,
+    ) -> EvaluationResult {
+        // A plain loop rather than `collect`, whose adapters would add
+        // several more frames to every recursive call in debug builds.
+        let mut values = Vec::with_capacity(args.len());
+
+        for arg in args {
+            values.push(arg.evaluate(interpreter)?);
+        }
+
+        match values.iter().find(|x| x.is_error()) {
+            Some(error) => Ok(error.clone()),
+            None => implementation.call(interpreter, values),
+        }
     }
 
-    pub fn register<T>(&mut self, function_name: &String, argument_count: u16, builtin: T)
-    where
-        T: Fn(Vec<Object>) -> Object + 'static,
-    {
-        self.variable_set(
-            function_name,
-            Object::Function {
-                argument_count,
-                implementation: FunctionImplementation::Builtin(Rc::new(builtin)),
+    fn evaluate_if(
+        condition: &Expression,
+        if_true: &Expression,
+        otherwise: Option<&Expression>,
+        interpreter: &mut Bloodbath,
+    ) -> EvaluationResult {
+        match condition.evaluate(interpreter)? {
+            error @ Object::Error(_) => Ok(error),
+            condition if condition.is_truthy() => if_true.evaluate(interpreter),
+            _ => match otherwise {
+                Some(otherwise) => otherwise.evaluate(interpreter),
+                None => Ok(Object::Noop),
             },
-        );
+        }
     }
 
-    fn expect_keyword(
-        &mut self,
-        tokens: &mut Vec<Token>,
-        expected_name: &str,
-    ) -> Result<(), ParserError> {
-        let err = ParserError::ExpectedAnIdentifier(format!("Keyword `{}`", expected_name));
-
-        if tokens.is_empty() {
-            return Err(err);
+    fn evaluate_cond(
+        branches: &[(Expression, Expression)],
+        otherwise: Option<&Expression>,
+        interpreter: &mut Bloodbath,
+    ) -> EvaluationResult {
+        for (condition, result) in branches {
+            match condition.evaluate(interpreter)? {
+                error @ Object::Error(_) => return Ok(error),
+                condition if condition.is_truthy() => return result.evaluate(interpreter),
+                _ => {}
+            }
         }
 
-        match tokens.remove(0) {
-            Token::Identifier(name) => {
-                if name == expected_name.to_string() {
-                    Ok(())
-                } else {
-                    Err(err)
-                }
-            }
-            _ => Err(err),
+        match otherwise {
+            Some(otherwise) => otherwise.evaluate(interpreter),
+            None => Ok(Object::Noop),
         }
     }
 
-    fn check_keyword(&mut self, tokens: &mut Vec<Token>, expected_name: &str) -> bool {
-        !tokens.is_empty()
-            && match &tokens[0] {
-                Token::Identifier(name) => {
-                    if *name == expected_name.to_string() {
-                        tokens.remove(0);
-                        true
-                    } else {
-                        false
-                    }
-                }
-                _ => false,
-            }
+    // Both return the last operand they evaluated, like in Lisp.
+    fn evaluate_and(
+        left: &Expression,
+        right: &Expression,
+        interpreter: &mut Bloodbath,
+    ) -> EvaluationResult {
+        match left.evaluate(interpreter)? {
+            error @ Object::Error(_) => Ok(error),
+            left if left.is_truthy() => right.evaluate(interpreter),
+            left => Ok(left),
+        }
     }
 
-    fn parse_variable(&mut self, name: &String, tokens: &mut Vec<Token>) -> ParserResult {
-        let variable_value = self.variable_get(&name);
+    fn evaluate_or(
+        left: &Expression,
+        right: &Expression,
+        interpreter: &mut Bloodbath,
+    ) -> EvaluationResult {
+        match left.evaluate(interpreter)? {
+            error @ Object::Error(_) => Ok(error),
+            left if left.is_truthy() => Ok(left),
+            _ => right.evaluate(interpreter),
+        }
+    }
 
-        match variable_value {
-            Object::Function {
-                argument_count,
-                implementation,
-            } => {
-                let mut arguments = Vec::new();
+    fn evaluate_while(
+        condition: &Expression,
+        body: &Expression,
+        interpreter: &mut Bloodbath,
+    ) -> EvaluationResult {
+        let mut result = Object::Noop;
 
-                for count in 0..argument_count {
-                    if tokens.is_empty() {
-                        return Err(ParserError::ExpectedAnExpression(format!(
-                            "Expected {} arguments after `{}`, got {}",
-                            argument_count, name, count
-                        )));
-                    }
+        loop {
+            match condition.evaluate(interpreter)? {
+                error @ Object::Error(_) => return Ok(error),
+                condition if condition.is_truthy() => {}
+                _ => break,
+            }
 
-                    arguments.push(self.parse_expression(tokens)?);
-                }
+            result = body.evaluate(interpreter)?;
 
-                Ok(Expression::FunctionCall(implementation, arguments))
+            if result.is_error() {
+                break;
             }
-            _ => Ok(Expression::Variable(name.clone())),
-        }
-    }
-
-    fn parse_compound(&mut self, tokens: &mut Vec<Token>) -> ParserResult {
-        if tokens.is_empty() {
-            return Err(ParserError::UnterminatedCompoundExpression);
         }
 
-        let mut expressions = Vec::new();
+        Ok(result)
+    }
 
-        loop {
-            if tokens[0] == Token::RightBrace {
-                tokens.remove(0);
-                return Ok(Expression::Compound(expressions));
+    fn evaluate_repeat(
+        count: &Expression,
+        body: &Expression,
+        interpreter: &mut Bloodbath,
+    ) -> EvaluationResult {
+        let count = match count.evaluate(interpreter)? {
+            error @ Object::Error(_) => return Ok(error),
+            Object::Integer(count) if count >= 0 => count,
+            other => {
+                return Err(RuntimeError::InvalidArgument(format!(
+                    "`repeat` expects a non-negative integer, got {}",
+                    other
+                )))
             }
+        };
+
+        let mut result = Object::Noop;
 
-            expressions.push(self.parse_expression(tokens)?);
+        for _ in 0..count {
+            result = body.evaluate(interpreter)?;
 
-            if tokens.is_empty() {
-                return Err(ParserError::UnterminatedCompoundExpression);
+            if result.is_error() {
+                break;
             }
         }
+
+        Ok(result)
     }
 
-    fn parse_identity(&mut self, tokens: &mut Vec<Token>) -> ParserResult {
-        if tokens.is_empty() {
-            return Err(ParserError::ExpectedAnExpression(
-                "`identity` must be followed by a constant or a variable name".into(),
-            ));
+    fn evaluate_let(
+        name: &str,
+        value: &Expression,
+        body: &Expression,
+        interpreter: &mut Bloodbath,
+    ) -> EvaluationResult {
+        let value = value.evaluate(interpreter)?;
+
+        if value.is_error() {
+            return Ok(value);
         }
 
-        return match tokens.remove(0) {
-            Token::Identifier(name) => {
-                if name == "noop" {
-                    Ok(Expression::Constant(Object::Noop))
-                } else {
-                    Ok(Expression::Variable(name))
-                }
+        interpreter.with_bindings(vec![(name.to_string(), value)], |interpreter| {
+            body.evaluate(interpreter)
+        })
+    }
+
+    fn evaluate_for_each(
+        name: &str,
+        items: &Expression,
+        body: &Expression,
+        interpreter: &mut Bloodbath,
+    ) -> EvaluationResult {
+        let items = match items.evaluate(interpreter)? {
+            error @ Object::Error(_) => return Ok(error),
+            Object::List(items) => items,
+            other => {
+                return Err(RuntimeError::InvalidArgument(format!(
+                    "`for` expects a list, got {}",
+                    other.type_with_article()
+                )))
             }
-            Token::IntegerConstant(value) => Ok(Expression::Constant(Object::Integer(value))),
-            Token::FloatConstant(value) => Ok(Expression::Constant(Object::Float(value))),
-            Token::LeftBrace | Token::RightBrace => Err(ParserError::UnexpectedBrace),
         };
-    }
 
-    fn parse_set(&mut self, tokens: &mut Vec<Token>) -> ParserResult {
-        let usage =
-            "`set` must be followed by a variable name and the variable's new value".to_string();
+        for item in items.iter() {
+            let result = interpreter
+                .with_bindings(vec![(name.to_string(), item.clone())], |interpreter| {
+                    body.evaluate(interpreter)
+                })?;
 
-        if tokens.is_empty() {
-            return Err(ParserError::ExpectedAnIdentifier(usage));
+            if result.is_error() {
+                return Ok(result);
+            }
         }
 
-        let variable_name = match tokens.remove(0) {
-            Token::Identifier(name) => name,
-            _ => return Err(ParserError::ExpectedAnIdentifier(usage)),
-        };
+        Ok(Object::Noop)
+    }
 
-        if tokens.is_empty() {
-            return Err(ParserError::ExpectedAnExpression(usage));
-        }
+    fn evaluate_try(
+        body: &Expression,
+        handler: &Expression,
+        interpreter: &mut Bloodbath,
+    ) -> EvaluationResult {
+        let message = match body.evaluate(interpreter) {
+            Ok(Object::Error(message)) => message,
+            // Limits and cancellation must stay in force.
+            Err(
+                err @ (RuntimeError::Cancelled
+                | RuntimeError::StepLimitExceeded
+                | RuntimeError::Timeout),
+            ) => return Err(err),
+            Err(err) => err.to_string(),
+            Ok(result) => return Ok(result),
+        };
 
-        let new_value = self.parse_expression(tokens)?;
+        let binding = (
+            ERROR_MESSAGE_VARIABLE.to_string(),
+            Object::String(message.into()),
+        );
 
-        Ok(Expression::Set(variable_name, Box::new(new_value)))
+        interpreter.with_bindings(vec![binding], |interpreter| handler.evaluate(interpreter))
     }
+}
 
-    fn parse_if(&mut self, tokens: &mut Vec<Token>) -> ParserResult {
-        if tokens.is_empty() {
-            return Err(ParserError::ExpectedAnExpression(
-                "`if` must be followed by a condition".into(),
-            ));
-        }
-
-        let condition = Box::new(self.parse_expression(tokens)?);
+#[derive(Debug, PartialEq, Clone)]
+pub enum RuntimeError {
+    Cancelled,
+    InvalidArgument(String),
+    IoFailed(String),
+    RecursionLimitExceeded,
+    StepLimitExceeded,
+    Timeout,
+    AssertionFailed(String),
+    UndefinedVariable(String),
+    Thrown(String),
+}
 
-        self.expect_keyword(tokens, "then")?;
+#[derive(Debug, PartialEq)]
+pub enum ParserError {
+    ReadingFailed(ReaderError),
+    EvaluationFailed(RuntimeError),
+    ExpectedAnExpression(String),
+    ExpectedAnIdentifier(String),
+    // The function name, how many arguments it takes and how many it got.
+    NotEnoughArguments(String, Arity, usize),
+    UnterminatedCompoundExpression,
+    UnterminatedList,
+    UnexpectedBrace,
+    UnexpectedBracket,
+    UnexpectedTerminator,
+    ExpectedThen,
+}
 
-        if tokens.is_empty() {
-            return Err(ParserError::ExpectedAnExpression(
-                "`then` must be followed by an expression".into(),
-            ));
+impl Display for RuntimeError {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            Self::Cancelled => write!(formatter, "Evaluation cancelled"),
+            Self::InvalidArgument(message) => write!(formatter, "{}", message),
+            Self::IoFailed(message) => write!(formatter, "IO error: {}", message),
+            Self::RecursionLimitExceeded => write!(formatter, "Maximum recursion depth exceeded"),
+            Self::StepLimitExceeded => write!(formatter, "Step limit exceeded"),
+            Self::Timeout => write!(formatter, "Evaluation timed out"),
+            Self::AssertionFailed(message) => write!(formatter, "{}", message),
+            Self::UndefinedVariable(name) => write!(formatter, "`{}` is not defined", name),
+            Self::Thrown(message) => write!(formatter, "{}", message),
         }
+    }
+}
 
-        let if_true = Box::new(self.parse_expression(tokens)?);
+impl std::error::Error for RuntimeError {}
 
-        let otherwise = if self.check_keyword(tokens, "else") {
-            if tokens.is_empty() {
-                return Err(ParserError::ExpectedAnExpression(
-                    "`else` must be followed by an expression".into(),
-                ));
+impl Display for ParserError {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            Self::ReadingFailed(err) => write!(formatter, "{}", err),
+            Self::EvaluationFailed(err) => write!(formatter, "{}", err),
+            Self::ExpectedAnExpression(message) | Self::ExpectedAnIdentifier(message) => {
+                write!(formatter, "{}", message)
             }
-
-            Some(Box::new(self.parse_expression(tokens)?))
-        } else {
-            None
-        };
-
-        Ok(Expression::If(condition, if_true, otherwise))
+            Self::NotEnoughArguments(name, argument_count, count) => write!(
+                formatter,
+                "`{}` expects {}, got {}",
+                name,
+                argument_count.arguments(),
+                count
+            ),
+            Self::UnterminatedCompoundExpression => write!(formatter, "Missing a closing `}}`"),
+            Self::UnterminatedList => write!(formatter, "Missing a closing `]`"),
+            Self::UnexpectedBrace => write!(formatter, "Unexpected brace"),
+            Self::UnexpectedBracket => write!(formatter, "Unexpected bracket"),
+            Self::UnexpectedTerminator => write!(formatter, "Unexpected `;`"),
+            Self::ExpectedThen => write!(formatter, "Expected `then` after the `if` condition"),
+        }
     }
+}
 
-    fn parse_expression(&mut self, tokens: &mut Vec<Token>) -> ParserResult {
-        match tokens.remove(0) {
-            Token::Identifier(name) => match name.as_str() {
-                "noop" => Ok(Expression::Constant(Object::Noop)),
-                "identity" => self.parse_identity(tokens),
-                "set" => self.parse_set(tokens),
-                "if" => self.parse_if(tokens),
-                _ => self.parse_variable(&name, tokens),
-            },
-            Token::IntegerConstant(value) => Ok(Expression::Constant(Object::Integer(value))),
-            Token::FloatConstant(value) => Ok(Expression::Constant(Object::Float(value))),
-            Token::LeftBrace => self.parse_compound(tokens),
-            Token::RightBrace => Err(ParserError::UnexpectedBrace),
+impl std::error::Error for ParserError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::ReadingFailed(err) => Some(err),
+            Self::EvaluationFailed(err) => Some(err),
+            _ => None,
         }
     }
+}
 
-    #[cfg(test)]
-    pub fn eval_str(&mut self, input: &str) -> Result<Object, ParserError> {
-        self.eval(input.into())
+impl From<ReaderError> for ParserError {
+    fn from(err: ReaderError) -> Self {
+        Self::ReadingFailed(err)
     }
+}
 
-    pub fn eval(&mut self, input: String) -> Result<Object, ParserError> {
-        let mut reader = Reader::new(input);
+// The bindings of an interpreter at some point, see `Bloodbath::snapshot`.
+#[derive(Clone)]
+pub struct EnvironmentSnapshot(HashMap<String, Rc<Object>>);
 
-        let mut tokens = reader
-            .tokenise()
-            .or_else(|err| Err(ParserError::ReadingFailed(err)))?;
+// Cloning takes a snapshot of the environment and settings. The random number
+// generator, clock, input and outputs are shared with the original.
+#[derive(Clone)]
+pub struct Bloodbath {
+    environment: HashMap<String, Rc<Object>>,
+    docs: HashMap<String, String>,
+    // The standard builtins as originally registered, for `restore_builtin`.
+    builtins: HashMap<String, Rc<Object>>,
+    random: Rc<RefCell<Random>>,
+    clock: Rc<dyn Clock>,
+    cancellation_flag: Option<Arc<AtomicBool>>,
+    input: Option<Rc<RefCell<dyn BufRead>>>,
+    output: Option<Rc<RefCell<dyn Write>>>,
+    call_depth: usize,
+    recursion_limit: usize,
+    steps: usize,
+    step_limit: Option<usize>,
+    deadline: Option<Instant>,
+    trace: bool,
+    trace_output: Option<Rc<RefCell<dyn Write>>>,
+    symbol_counter: u64,
+    // Whether `reset` should register the standard builtins again.
+    prelude: bool,
+    sandboxed: bool,
+    // Whether the expression being parsed may take optional arguments up to
+    // the end of its group, see `parse_operand`.
+    tail_position: bool,
+    // Functions bound by `def` while parsing, with the values they shadowed.
+    // They only last until the end of the enclosing group, see
+    // `forget_definitions`.
+    definitions: Vec<(String, Option<Rc<Object>>)>,
+}
 
-        let mut result = Object::Noop;
+// Calls recurse natively in the evaluator. Reaching this limit takes several
+// megabytes of stack in debug builds, more than a default 2 MiB thread has, so
+// embedders should either evaluate on a thread with `STACK_SIZE`, like the
+// binary does, or lower the limit with `set_recursion_limit`.
+pub const DEFAULT_RECURSION_LIMIT: usize = 1000;
 
-        while !tokens.is_empty() {
-            result = self.parse_expression(&mut tokens)?.evaluate(self);
-        }
+// Enough stack to reach `DEFAULT_RECURSION_LIMIT` even in debug builds.
+pub const STACK_SIZE: usize = 256 * 1024 * 1024;
 
-        Ok(result)
+// How many steps to take between checks of the `eval_with_timeout` deadline.
+const DEADLINE_CHECK_INTERVAL: usize = 1024;
+
+// Builtins that do I/O or depend on the outside world, left undefined in a
+// sandboxed interpreter.
+pub const IMPURE_BUILTINS: &[&str] = &[
+    "print",
+    "read-line",
+    "read-file",
+    "write-file",
+    "append-file",
+    "time",
+    "clock",
+    "rand",
+    "rand-int",
+    "shuffle",
+    "sample",
+];
+
+// The variable holding the error message inside a `catch` handler.
+pub const ERROR_MESSAGE_VARIABLE: &str = "error-message";
+
+const BUILTIN_DOCS: &[(&str, &str)] = &[
+    ("+", "Adds two or more numbers together."),
+    ("-", "Subtracts the second number from the first."),
+    ("neg", "Negates a number."),
+    ("*", "Multiplies two or more numbers together."),
+    (
+        "/",
+        "Divides the first number by the second, always giving a float.",
+    ),
+    (
+        "//",
+        "Divides the first integer by the second, rounding down.",
+    ),
+    ("min", "Returns the smaller of two numbers."),
+    ("max", "Returns the larger of two numbers."),
+    (
+        "round",
+        "Rounds a number half to even, optionally to the given number of decimal places.",
+    ),
+    ("sin", "Returns the sine of an angle in radians."),
+    ("cos", "Returns the cosine of an angle in radians."),
+    ("tan", "Returns the tangent of an angle in radians."),
+    (
+        "log",
+        "Returns the natural logarithm of a positive number, or its logarithm in the given base.",
+    ),
+    ("exp", "Returns e raised to the given power."),
+    (
+        "=",
+        "Checks whether two numbers are equal. NaN is equal to itself.",
+    ),
+    (
+        "<",
+        "Checks whether the first number is less than the second.",
+    ),
+    (
+        ">",
+        "Checks whether the first number is greater than the second.",
+    ),
+    (
+        "<=",
+        "Checks whether the first number is at most the second.",
+    ),
+    (
+        ">=",
+        "Checks whether the first number is at least the second.",
+    ),
+    ("nan?", "Checks whether a number is NaN."),
+    (
+        "infinite?",
+        "Checks whether a number is positive or negative infinity.",
+    ),
+    (
+        "eq?",
+        "Checks whether two values are the same, without converting between types.",
+    ),
+    (
+        "ne?",
+        "Checks whether two values differ, without converting between types.",
+    ),
+    ("band", "Bitwise AND of two integers."),
+    ("bor", "Bitwise OR of two integers."),
+    ("bxor", "Bitwise XOR of two integers."),
+    ("shl", "Shifts an integer left by the given number of bits."),
+    (
+        "shr",
+        "Shifts an integer right by the given number of bits.",
+    ),
+    ("concat", "Joins two strings."),
+    ("length", "Returns the length of a string or a list."),
+    (
+        "substring",
+        "Returns the characters of a string from a start index up to an end index.",
+    ),
+    (
+        "char-at",
+        "Returns the character of a string at the given index.",
+    ),
+    ("string->list", "Splits a string into a list of characters."),
+    ("list->string", "Joins a list of characters into a string."),
+    (
+        "join",
+        "Joins a list of strings into one, with a separator between them.",
+    ),
+    ("upper", "Converts a string to upper case."),
+    ("lower", "Converts a string to lower case."),
+    ("trim", "Removes whitespace from both ends of a string."),
+    (
+        "split",
+        "Splits a string into a list of strings at every separator.",
+    ),
+    ("first", "Returns the first item of a non-empty list."),
+    (
+        "rest",
+        "Returns every item of a non-empty list except the first.",
+    ),
+    ("cons", "Prepends a value to a list."),
+    ("append", "Joins two lists into a new list."),
+    ("map-new", "Creates an empty map."),
+    (
+        "map-get",
+        "Returns the value stored under a key in a map, or noop if there is none.",
+    ),
+    (
+        "map-set",
+        "Returns a copy of a map with a key set to a value.",
+    ),
+    ("map-has", "Checks whether a map contains a key."),
+    (
+        "keys",
+        "Returns a list of the keys of a map, in no particular order.",
+    ),
+    (
+        "values",
+        "Returns a list of the values of a map, in the same order as `keys`.",
+    ),
+    (
+        "format",
+        "Replaces each `{}` in a string with the next argument.",
+    ),
+    ("error", "Creates an error value with the given message."),
+    (
+        "throw",
+        "Raises an error with the given message, to be caught by `try`.",
+    ),
+    (
+        "default",
+        "Returns the first value, or the second one if the first is noop.",
+    ),
+    ("assert", "Fails unless the value is truthy."),
+    ("type-of", "Returns the name of a value's type as a string."),
+    (
+        "str",
+        "Converts any value to a string, the way `print` would show it.",
+    ),
+    (
+        "parse-number",
+        "Parses a string as an integer or float literal.",
+    ),
+    (
+        "arity",
+        "Returns the minimum number of arguments a function takes, as in `arity quote +`.",
+    ),
+    ("assert-eq", "Fails unless the two values are the same."),
+    (
+        "read-line",
+        "Reads a line of input, or returns noop at the end of input.",
+    ),
+    ("print", "Prints a value followed by a newline."),
+    ("read-file", "Returns the contents of a file as a string."),
+    (
+        "write-file",
+        "Replaces the contents of a file with a string.",
+    ),
+    ("append-file", "Appends a string to the end of a file."),
+    (
+        "time",
+        "Returns the number of seconds since the Unix epoch.",
+    ),
+    (
+        "clock",
+        "Returns the number of seconds since the interpreter started, for measuring durations.",
+    ),
+    ("gensym", "Returns a fresh unique name, such as `g#1`."),
+    (
+        "call",
+        "Calls a function with the items of a list as its arguments, as in `call quote + [1 2]`.",
+    ),
+    (
+        "map",
+        "Applies a function to every item of a list, as in `map quote neg [1 2]`.",
+    ),
+    (
+        "filter",
+        "Keeps the items of a list for which a predicate is truthy, as in `filter quote nan? [1 nan]`.",
+    ),
+    (
+        "reduce",
+        "Folds a list from the left, starting with an initial value, as in `reduce quote + 0 [1 2]`.",
+    ),
+    ("shuffle", "Returns the items of a list in random order."),
+    (
+        "sample",
+        "Picks the given number of random items from a list.",
+    ),
+    (
+        "rand",
+        "Returns a random float between 0 (inclusive) and 1 (exclusive).",
+    ),
+    (
+        "rand-int",
+        "Returns a random integer between two integers, both inclusive.",
+    ),
+];
+
+pub struct UserFunction {
+    parameters: Vec<String>,
+    body: RefCell<Expression>,
+}
+
+impl UserFunction {
+    pub fn call(&self, interpreter: &mut Bloodbath, arguments: Vec<Object>) -> EvaluationResult {
+        let bindings = self.parameters.iter().cloned().zip(arguments).collect();
+
+        interpreter.with_bindings(bindings, |interpreter| {
+            self.body.borrow().evaluate(interpreter)
+        })
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+type ParserResult = Result<Expression, ParserError>;
+pub type EvaluationResult = Result<Object, RuntimeError>;
 
-    #[test]
-    fn test_eval() {
-        let mut bloodbath = Bloodbath::new();
+impl Default for Bloodbath {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-        assert_eq!(bloodbath.eval_str("noop"), Ok(Object::Noop),);
-        assert_eq!(bloodbath.eval_str("identity 1"), Ok(Object::Integer(1)));
-        assert_eq!(bloodbath.eval_str("+ 1 2"), Ok(Object::Integer(3)));
-        assert_eq!(bloodbath.eval_str("+ 1 + 1 1"), Ok(Object::Integer(3)));
-        assert_eq!(bloodbath.eval_str("+ + 1 1 1"), Ok(Object::Integer(3)));
+impl Bloodbath {
+    pub fn new() -> Self {
+        Self::with_prelude()
+    }
 
-        assert_eq!(
-            bloodbath.eval_str("if 0 then 42 else 0"),
-            Ok(Object::Integer(42))
-        );
+    // An interpreter with every standard builtin.
+    pub fn with_prelude() -> Self {
+        let mut us = Self::empty();
+        us.prelude = true;
+        us.register_builtins();
+        us
+    }
 
-        assert_eq!(
-            bloodbath.eval_str("if noop then 42 else 0"),
-            Ok(Object::Integer(0))
-        );
+    // An interpreter with every builtin except those in `IMPURE_BUILTINS`, for
+    // running untrusted scripts alongside the step and recursion limits.
+    pub fn sandboxed() -> Self {
+        let mut us = Self::empty();
+        us.prelude = true;
+        us.sandboxed = true;
+        us.register_builtins();
+        us
+    }
 
-        assert_eq!(bloodbath.eval_str("if noop then 42"), Ok(Object::Noop));
+    // An interpreter with no bindings at all, for sandboxes that only expose
+    // what the embedder registers.
+    pub fn empty() -> Self {
+        Self {
+            environment: HashMap::new(),
+            docs: HashMap::new(),
+            builtins: HashMap::new(),
+            random: Rc::new(RefCell::new(Random::from_time())),
+            clock: Rc::new(SystemClock::new()),
+            cancellation_flag: None,
+            input: None,
+            output: None,
+            call_depth: 0,
+            recursion_limit: DEFAULT_RECURSION_LIMIT,
+            steps: 0,
+            step_limit: None,
+            deadline: None,
+            trace: false,
+            trace_output: None,
+            symbol_counter: 0,
+            prelude: false,
+            sandboxed: false,
+            tail_position: true,
+            definitions: Vec::new(),
+        }
+    }
 
-        assert_eq!(
-            bloodbath.eval_str("if noop then 1 else if noop then 2 else 3"),
-            Ok(Object::Integer(3))
+    fn register_builtins(&mut self) {
+        self.register("+", Arity::AtLeast(2), crate::builtins::add);
+        self.register("-", Arity::Exact(2), crate::builtins::sub);
+        self.register("neg", Arity::Exact(1), crate::builtins::neg);
+        self.register("*", Arity::AtLeast(2), crate::builtins::mul);
+        self.register("/", Arity::Exact(2), crate::builtins::div);
+        self.register("//", Arity::Exact(2), crate::builtins::floor_div);
+        self.register("min", Arity::Exact(2), crate::builtins::min);
+        self.register("max", Arity::Exact(2), crate::builtins::max);
+        self.register("round", Arity::AtLeast(1), crate::builtins::round);
+        self.register("sin", Arity::Exact(1), crate::builtins::sin);
+        self.register("cos", Arity::Exact(1), crate::builtins::cos);
+        self.register("tan", Arity::Exact(1), crate::builtins::tan);
+        self.register("log", Arity::AtLeast(1), crate::builtins::log);
+        self.register("exp", Arity::Exact(1), crate::builtins::exp);
+        self.register("=", Arity::Exact(2), crate::builtins::equal);
+        self.register("<", Arity::Exact(2), crate::builtins::less);
+        self.register(">", Arity::Exact(2), crate::builtins::greater);
+        self.register("<=", Arity::Exact(2), crate::builtins::less_or_equal);
+        self.register(">=", Arity::Exact(2), crate::builtins::greater_or_equal);
+        self.register("nan?", Arity::Exact(1), crate::builtins::is_nan);
+        self.register("infinite?", Arity::Exact(1), crate::builtins::is_infinite);
+        self.register("eq?", Arity::Exact(2), crate::builtins::eq);
+        self.register("ne?", Arity::Exact(2), crate::builtins::ne);
+        self.register("band", Arity::Exact(2), crate::builtins::band);
+        self.register("bor", Arity::Exact(2), crate::builtins::bor);
+        self.register("bxor", Arity::Exact(2), crate::builtins::bxor);
+        self.register("shl", Arity::Exact(2), crate::builtins::shl);
+        self.register("shr", Arity::Exact(2), crate::builtins::shr);
+        self.register("concat", Arity::Exact(2), crate::builtins::concat);
+        self.register("length", Arity::Exact(1), crate::builtins::length);
+        self.register("char-at", Arity::Exact(2), crate::builtins::char_at);
+        self.register(
+            "string->list",
+            Arity::Exact(1),
+            crate::builtins::string_to_list,
+        );
+        self.register(
+            "list->string",
+            Arity::Exact(1),
+            crate::builtins::list_to_string,
         );
+        self.register("join", Arity::Exact(2), crate::builtins::join);
+        self.register("split", Arity::Exact(2), crate::builtins::split);
+        self.register("upper", Arity::Exact(1), crate::builtins::upper);
+        self.register("lower", Arity::Exact(1), crate::builtins::lower);
+        self.register("trim", Arity::Exact(1), crate::builtins::trim);
+        self.register("first", Arity::Exact(1), crate::builtins::first);
+        self.register("rest", Arity::Exact(1), crate::builtins::rest);
+        self.register("cons", Arity::Exact(2), crate::builtins::cons);
+        self.register("append", Arity::Exact(2), crate::builtins::append);
+        self.register("map-new", Arity::Exact(0), crate::builtins::map_new);
+        self.register("map-get", Arity::Exact(2), crate::builtins::map_get);
+        self.register("map-set", Arity::Exact(3), crate::builtins::map_set);
+        self.register("map-has", Arity::Exact(2), crate::builtins::map_has);
+        self.register("keys", Arity::Exact(1), crate::builtins::keys);
+        self.register("values", Arity::Exact(1), crate::builtins::values);
+        self.register("substring", Arity::Exact(3), crate::builtins::substring);
+        self.register("format", Arity::AtLeast(1), crate::builtins::format);
+        self.register("error", Arity::Exact(1), crate::builtins::error);
+        self.register("throw", Arity::Exact(1), crate::builtins::throw);
+        self.register("default", Arity::Exact(2), crate::builtins::default);
+        self.register("type-of", Arity::Exact(1), crate::builtins::type_of);
+        self.register("str", Arity::Exact(1), crate::builtins::str);
+        self.register(
+            "parse-number",
+            Arity::Exact(1),
+            crate::builtins::parse_number,
+        );
+        self.register("arity", Arity::Exact(1), crate::builtins::arity);
+        self.register("assert", Arity::Exact(1), crate::builtins::assert);
+        self.register("assert-eq", Arity::Exact(2), crate::builtins::assert_eq);
+
+        self.register_float("nan", f64::NAN);
+        self.register_float("inf", f64::INFINITY);
+        self.register_float("pi", std::f64::consts::PI);
+
+        self.register_context("read-line", Arity::Exact(0), crate::builtins::read_line);
+        self.register("read-file", Arity::Exact(1), crate::builtins::read_file);
+        self.register("write-file", Arity::Exact(2), crate::builtins::write_file);
+        self.register("append-file", Arity::Exact(2), crate::builtins::append_file);
+        self.register_context("print", Arity::Exact(1), crate::builtins::print);
+        self.register_context("time", Arity::Exact(0), crate::builtins::time);
+        self.register_context("clock", Arity::Exact(0), crate::builtins::clock);
+        self.register_context("gensym", Arity::Exact(0), crate::builtins::gensym);
+        self.register_context("call", Arity::Exact(2), crate::builtins::call);
+        self.register_context("map", Arity::Exact(2), crate::builtins::map);
+        self.register_context("filter", Arity::Exact(2), crate::builtins::filter);
+        self.register_context("reduce", Arity::Exact(3), crate::builtins::reduce);
+
+        let random = self.random.clone();
+        self.register("shuffle", Arity::Exact(1), move |args| {
+            crate::builtins::shuffle(&random, args)
+        });
+
+        let random = self.random.clone();
+        self.register("rand", Arity::Exact(0), move |args| {
+            crate::builtins::rand(&random, args)
+        });
+
+        let random = self.random.clone();
+        self.register("rand-int", Arity::Exact(2), move |args| {
+            crate::builtins::rand_int(&random, args)
+        });
+
+        let random = self.random.clone();
+        self.register("sample", Arity::Exact(2), move |args| {
+            crate::builtins::sample(&random, args)
+        });
+
+        for (name, doc) in BUILTIN_DOCS {
+            self.docs.insert(name.to_string(), doc.to_string());
+        }
+
+        if self.sandboxed {
+            for name in IMPURE_BUILTINS {
+                self.environment.remove(*name);
+                self.docs.remove(*name);
+            }
+        }
+
+        self.builtins = self.environment.clone();
     }
 
-    #[test]
-    fn test_variables() {
-        let mut bloodbath = Bloodbath::new();
+    // Rebinds `name` to its standard builtin, undoing any `set` that shadowed
+    // it. Returns false if there is no such builtin.
+    pub fn restore_builtin(&mut self, name: &str) -> bool {
+        match self.builtins.get(name) {
+            Some(builtin) => {
+                self.environment.insert(name.to_string(), builtin.clone());
+                true
+            }
+            None => false,
+        }
+    }
 
-        assert_eq!(bloodbath.eval_str("set a 10"), Ok(Object::Integer(10)));
-        assert_eq!(bloodbath.eval_str("set b 20"), Ok(Object::Integer(20)));
-        assert_eq!(bloodbath.eval_str("set c + a b"), Ok(Object::Integer(30)));
-        assert_eq!(bloodbath.eval_str("set + c"), Ok(Object::Integer(30)));
-        assert_eq!(bloodbath.eval_str("+"), Ok(Object::Integer(30)));
-        assert_eq!(bloodbath.eval_str("identity +"), Ok(Object::Integer(30)));
+    // Captures the current bindings so that `restore` can undo any changes
+    // made after this point. Values are shared, not copied.
+    pub fn snapshot(&self) -> EnvironmentSnapshot {
+        EnvironmentSnapshot(self.environment.clone())
     }
 
-    #[test]
-    fn test_compound() {
-        let mut bloodbath = Bloodbath::new();
+    pub fn restore(&mut self, snapshot: EnvironmentSnapshot) {
+        self.environment = snapshot.0;
+    }
 
-        assert_eq!(bloodbath.eval_str("{1 2 3}"), Ok(Object::Integer(3)));
-        assert_eq!(bloodbath.eval_str("{set a 5}"), Ok(Object::Integer(5)));
-        assert_eq!(bloodbath.eval_str("{set b + 5 a}"), Ok(Object::Integer(10)));
+    // Removes every binding, including builtins registered by the embedder,
+    // and registers the standard builtins again unless created with `empty`.
+    pub fn reset(&mut self) {
+        self.environment.clear();
+        self.docs.clear();
+        self.symbol_counter = 0;
 
-        assert_eq!(
-            bloodbath.eval_str("if {a noop} then 1 else 0"),
+        if self.prelude {
+            self.register_builtins();
+        }
+    }
+
+    pub fn with_cancellation_flag(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.cancellation_flag = Some(flag);
+        self
+    }
+
+    pub fn with_input<T>(mut self, input: T) -> Self
+    where
+        T: BufRead + 'static,
+    {
+        self.input = Some(Rc::new(RefCell::new(input)));
+        self
+    }
+
+    pub fn with_clock<T>(mut self, clock: T) -> Self
+    where
+        T: Clock + 'static,
+    {
+        self.clock = Rc::new(clock);
+        self
+    }
+
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    // Reads a line from the configured input, or from stdin if there is none.
+    // Returns `None` at the end of input.
+    pub fn read_line(&mut self) -> Option<String> {
+        let mut line = String::new();
+
+        let result = match &mut self.input {
+            Some(input) => input.borrow_mut().read_line(&mut line),
+            None => std::io::stdin().read_line(&mut line),
+        };
+
+        match result {
+            Ok(0) | Err(_) => None,
+            Ok(_) => {
+                if line.ends_with('\n') {
+                    line.pop();
+
+                    if line.ends_with('\r') {
+                        line.pop();
+                    }
+                }
+
+                Some(line)
+            }
+        }
+    }
+
+    pub fn with_output<T>(mut self, output: T) -> Self
+    where
+        T: Write + 'static,
+    {
+        self.output = Some(Rc::new(RefCell::new(output)));
+        self
+    }
+
+    // Writes a line to the configured output, or to stdout if there is none.
+    pub fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        match &mut self.output {
+            Some(output) => writeln!(output.borrow_mut(), "{}", line),
+            None => writeln!(std::io::stdout(), "{}", line),
+        }
+    }
+
+    pub fn with_trace_output<T>(mut self, output: T) -> Self
+    where
+        T: Write + 'static,
+    {
+        self.trace_output = Some(Rc::new(RefCell::new(output)));
+        self
+    }
+
+    // When tracing, every evaluated expression is written to the trace output,
+    // or to stderr if there is none, along with its result.
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.trace
+    }
+
+    fn write_trace(&mut self, expression: &Expression, result: &EvaluationResult) {
+        let result = match result {
+            Ok(object) => format!("{:?}", object),
+            Err(err) => err.to_string(),
+        };
+
+        let indent = "  ".repeat(self.call_depth);
+        let line = format!("{}{:?} => {}", indent, expression, result);
+
+        // Tracing is a debugging aid, so failing to write it isn't fatal.
+        let _ = match &mut self.trace_output {
+            Some(output) => writeln!(output.borrow_mut(), "{}", line),
+            None => writeln!(std::io::stderr(), "{}", line),
+        };
+    }
+
+    // Seeding also restarts `gensym`, so that seeded runs are reproducible.
+    pub fn seed(&mut self, seed: u64) {
+        self.random.borrow_mut().seed(seed);
+        self.symbol_counter = 0;
+    }
+
+    pub fn set_recursion_limit(&mut self, limit: usize) {
+        self.recursion_limit = limit;
+    }
+
+    // Limits how many expressions a single call to `eval` may evaluate. There
+    // is no limit by default.
+    pub fn set_step_limit(&mut self, limit: usize) {
+        self.step_limit = Some(limit);
+    }
+
+    fn count_step(&mut self) -> Result<(), RuntimeError> {
+        self.steps += 1;
+
+        if let Some(limit) = self.step_limit {
+            if self.steps > limit {
+                return Err(RuntimeError::StepLimitExceeded);
+            }
+        }
+
+        // Reading the time on every step would be too slow.
+        if let Some(deadline) = self.deadline {
+            if self.steps.is_multiple_of(DEADLINE_CHECK_INTERVAL) && Instant::now() >= deadline {
+                return Err(RuntimeError::Timeout);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_cancelled(&self) -> Result<(), RuntimeError> {
+        match &self.cancellation_flag {
+            Some(flag) if flag.load(Ordering::Relaxed) => Err(RuntimeError::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    // Values are shared with the environment, so reading a variable never
+    // copies it.
+    pub fn variable_get(&self, variable_name: &str) -> Option<Rc<Object>> {
+        self.environment.get(variable_name).cloned()
+    }
+
+    pub fn is_defined(&self, variable_name: &str) -> bool {
+        self.environment.contains_key(variable_name)
+    }
+
+    pub fn variable_set(&mut self, variable_name: &str, new_value: Object) {
+        self.environment
+            .insert(variable_name.to_string(), Rc::new(new_value));
+    }
+
+    // Calls a function bound to `function_name`, so that embedders can use
+    // functions defined by a script.
+    pub fn call_function(
+        &mut self,
+        function_name: &str,
+        arguments: Vec<Object>,
+    ) -> EvaluationResult {
+        let function = match self.environment.get(function_name) {
+            Some(function) => function.clone(),
+            None => return Err(RuntimeError::UndefinedVariable(function_name.into())),
+        };
+
+        match function.as_ref() {
+            Object::Function {
+                argument_count,
+                implementation,
+            } => {
+                if !argument_count.accepts(arguments.len()) {
+                    return Err(RuntimeError::InvalidArgument(format!(
+                        "`{}` expects {}, got {}",
+                        function_name,
+                        argument_count.arguments(),
+                        arguments.len()
+                    )));
+                }
+
+                implementation.call(self, arguments)
+            }
+            _ => Err(RuntimeError::InvalidArgument(format!(
+                "`{}` is not a function",
+                function_name
+            ))),
+        }
+    }
+
+    pub fn list_bindings(&self) -> Vec<(String, String)> {
+        let mut bindings: Vec<(String, String)> = self
+            .environment
+            .iter()
+            .map(|(name, value)| {
+                let description = match value.as_ref() {
+                    Object::Function {
+                        implementation: FunctionImplementation::User(_),
+                        ..
+                    } => value.to_string(),
+                    Object::Function { argument_count, .. } => {
+                        format!("<builtin/{}>", argument_count.abbreviated())
+                    }
+                    _ => format!("{:?}", value),
+                };
+
+                (name.clone(), description)
+            })
+            .collect();
+
+        bindings.sort();
+        bindings
+    }
+
+    // Binds each name to its value while running `action`, then restores
+    // whatever was bound to those names before.
+    fn with_bindings<T, F>(&mut self, bindings: Vec<(String, Object)>, action: F) -> T
+    where
+        F: FnOnce(&mut Self) -> T,
+    {
+        let previous: Vec<(String, Option<Rc<Object>>)> = bindings
+            .into_iter()
+            .map(|(name, value)| {
+                let previous = self.environment.insert(name.clone(), Rc::new(value));
+                (name, previous)
+            })
+            .collect();
+
+        let result = action(self);
+
+        for (name, previous) in previous.into_iter().rev() {
+            match previous {
+                Some(previous) => self.environment.insert(name, previous),
+                None => self.environment.remove(&name),
+            };
+        }
+
+        result
+    }
+
+    pub(crate) fn next_symbol(&mut self) -> u64 {
+        self.symbol_counter += 1;
+        self.symbol_counter
+    }
+
+    pub(crate) fn enter_call(&mut self) -> Result<(), RuntimeError> {
+        if self.call_depth >= self.recursion_limit {
+            return Err(RuntimeError::RecursionLimitExceeded);
+        }
+
+        self.call_depth += 1;
+        Ok(())
+    }
+
+    pub(crate) fn leave_call(&mut self) {
+        self.call_depth -= 1;
+    }
+
+    pub fn register<T>(&mut self, function_name: &str, argument_count: Arity, builtin: T)
+    where
+        T: Fn(Vec<Object>) -> EvaluationResult + 'static,
+    {
+        self.variable_set(
+            function_name,
+            Object::Function {
+                argument_count,
+                implementation: FunctionImplementation::Builtin(Rc::new(builtin)),
+            },
+        );
+    }
+
+    pub fn register_constant(&mut self, name: &str, value: Object) {
+        self.variable_set(name, value);
+    }
+
+    pub fn register_int(&mut self, name: &str, value: i64) {
+        self.register_constant(name, Object::Integer(value));
+    }
+
+    pub fn register_float(&mut self, name: &str, value: f64) {
+        self.register_constant(name, Object::Float(value));
+    }
+
+    pub fn register_str(&mut self, name: &str, value: &str) {
+        self.register_constant(name, Object::String(value.to_string().into()));
+    }
+
+    pub fn register_doc<T>(
+        &mut self,
+        function_name: &str,
+        argument_count: Arity,
+        doc: &str,
+        builtin: T,
+    ) where
+        T: Fn(Vec<Object>) -> EvaluationResult + 'static,
+    {
+        self.register(function_name, argument_count, builtin);
+        self.docs.insert(function_name.to_string(), doc.to_string());
+    }
+
+    pub fn describe(&self, name: &str) -> Option<String> {
+        self.docs.get(name).cloned()
+    }
+
+    pub fn register_context<T>(&mut self, function_name: &str, argument_count: Arity, builtin: T)
+    where
+        T: Fn(&mut Bloodbath, Vec<Object>) -> EvaluationResult + 'static,
+    {
+        self.variable_set(
+            function_name,
+            Object::Function {
+                argument_count,
+                implementation: FunctionImplementation::ContextBuiltin(Rc::new(builtin)),
+            },
+        );
+    }
+
+    fn expect_keyword(
+        &mut self,
+        tokens: &mut TokenStream,
+        expected_name: &str,
+    ) -> Result<(), ParserError> {
+        let err =
+            ParserError::ExpectedAnIdentifier(format!("Expected keyword `{}`", expected_name));
+
+        if tokens.is_empty()? {
+            return Err(err);
+        }
+
+        match tokens.next_token()? {
+            Token::Identifier(name) if name == expected_name => Ok(()),
+            _ => Err(err),
+        }
+    }
+
+    fn check_keyword(
+        &mut self,
+        tokens: &mut TokenStream,
+        expected_name: &str,
+    ) -> Result<bool, ParserError> {
+        match tokens.peek()? {
+            Some(Token::Identifier(name)) if name == expected_name => {
+                tokens.next_token()?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    // Calls in tail position consume optional arguments until the enclosing
+    // block, list or `if` clause ends, until a `;`, or until there is nothing
+    // left to read. They also stop at the end of the line, see `parse_variable`.
+    fn is_argument_end(&self, tokens: &mut TokenStream) -> Result<bool, ParserError> {
+        Ok(match tokens.peek()? {
+            None | Some(Token::RightBrace) | Some(Token::RightBracket) => true,
+            Some(Token::Terminator) => true,
+            Some(Token::Identifier(name)) => {
+                ["then", "else", "end", "catch"].contains(&name.as_str())
+            }
+            _ => false,
+        })
+    }
+
+    fn parse_variable(&mut self, name: &str, tokens: &mut TokenStream) -> ParserResult {
+        let Some(variable_value) = self.variable_get(name) else {
+            return Ok(Expression::Variable(name.to_string()));
+        };
+
+        match variable_value.as_ref() {
+            Object::Function {
+                argument_count,
+                implementation,
+            } => {
+                let argument_count = *argument_count;
+                let mut arguments = Vec::new();
+
+                let minimum = match argument_count {
+                    Arity::Exact(count) | Arity::AtLeast(count) => count,
+                    Arity::Variadic => 0,
+                };
+
+                let takes_extra = argument_count != Arity::Exact(minimum) && self.tail_position;
+
+                for count in 0..minimum {
+                    if self.is_argument_end(tokens)? {
+                        return Err(ParserError::NotEnoughArguments(
+                            name.to_string(),
+                            argument_count,
+                            count as usize,
+                        ));
+                    }
+
+                    let argument = if count + 1 == minimum && !takes_extra {
+                        self.parse_expression(tokens)?
+                    } else {
+                        self.parse_operand(tokens)?
+                    };
+
+                    arguments.push(argument);
+                }
+
+                if takes_extra {
+                    while !self.is_argument_end(tokens)? && !tokens.starts_line()? {
+                        arguments.push(self.parse_operand(tokens)?);
+                    }
+                }
+
+                Ok(Expression::FunctionCall(implementation.clone(), arguments))
+            }
+            _ => Ok(Expression::Variable(name.to_string())),
+        }
+    }
+
+    // Parses an operand that more of the same form follows. Calls in it only
+    // take their minimum number of arguments and leave the rest to the form,
+    // so `- + 1 2 3` is 0 and `[+ 1 2 3]` has two items.
+    fn parse_operand(&mut self, tokens: &mut TokenStream) -> ParserResult {
+        let tail_position = std::mem::replace(&mut self.tail_position, false);
+        let result = self.parse_expression(tokens);
+        self.tail_position = tail_position;
+        result
+    }
+
+    // Parses an expression that stands on its own, like a statement in a block,
+    // where calls may take optional arguments up to the end of the group.
+    fn parse_statement(&mut self, tokens: &mut TokenStream) -> ParserResult {
+        let tail_position = std::mem::replace(&mut self.tail_position, true);
+        let result = self.parse_expression(tokens);
+        self.tail_position = tail_position;
+        result
+    }
+
+    // Terminators between statements carry no meaning of their own.
+    fn skip_terminators(&self, tokens: &mut TokenStream) -> Result<(), ParserError> {
+        while tokens.peek()? == Some(&Token::Terminator) {
+            tokens.next_token()?;
+        }
+
+        Ok(())
+    }
+
+    fn parse_compound(&mut self, tokens: &mut TokenStream) -> ParserResult {
+        let mark = self.definitions.len();
+        let result = self.parse_compound_items(tokens);
+        self.forget_definitions(mark);
+        result
+    }
+
+    fn parse_compound_items(&mut self, tokens: &mut TokenStream) -> ParserResult {
+        let mut expressions = Vec::new();
+
+        loop {
+            self.skip_terminators(tokens)?;
+
+            if tokens.is_empty()? {
+                return Err(ParserError::UnterminatedCompoundExpression);
+            }
+
+            if tokens.peek()? == Some(&Token::RightBrace) {
+                tokens.next_token()?;
+                return Ok(Expression::Compound(expressions));
+            }
+
+            expressions.push(self.parse_statement(tokens)?);
+        }
+    }
+
+    // A function is bound as soon as its `def` is parsed, so that the rest of
+    // the group can call it. Once the group is parsed, the binding is undone
+    // and it's left to the `def` to bind the function when it's evaluated.
+    fn forget_definitions(&mut self, mark: usize) {
+        for (name, previous) in self.definitions.split_off(mark).into_iter().rev() {
+            match previous {
+                Some(previous) => self.environment.insert(name, previous),
+                None => self.environment.remove(&name),
+            };
+        }
+    }
+
+    fn parse_list(&mut self, tokens: &mut TokenStream) -> ParserResult {
+        let mut expressions = Vec::new();
+
+        loop {
+            if tokens.is_empty()? {
+                return Err(ParserError::UnterminatedList);
+            }
+
+            if tokens.peek()? == Some(&Token::RightBracket) {
+                tokens.next_token()?;
+                return Ok(Expression::List(expressions));
+            }
+
+            expressions.push(self.parse_operand(tokens)?);
+        }
+    }
+
+    fn parse_identity(&mut self, tokens: &mut TokenStream) -> ParserResult {
+        if tokens.is_empty()? {
+            return Err(ParserError::ExpectedAnExpression(
+                "`identity` must be followed by a constant or a variable name".into(),
+            ));
+        }
+
+        match tokens.next_token()? {
+            Token::Identifier(name) => {
+                if name == "noop" {
+                    Ok(Expression::Constant(Object::Noop))
+                } else {
+                    Ok(Expression::Variable(name))
+                }
+            }
+            Token::IntegerConstant(value) => Ok(Expression::Constant(Object::Integer(value))),
+            Token::FloatConstant(value) => Ok(Expression::Constant(Object::Float(value))),
+            Token::StringConstant(value) => Ok(Expression::Constant(Object::String(value.into()))),
+            Token::CharConstant(value) => Ok(Expression::Constant(Object::Char(value))),
+            Token::Boolean(value) => Ok(Expression::Constant(Object::Boolean(value))),
+            Token::LeftBrace | Token::RightBrace => Err(ParserError::UnexpectedBrace),
+            Token::LeftBracket | Token::RightBracket => Err(ParserError::UnexpectedBracket),
+            Token::Terminator => Err(ParserError::UnexpectedTerminator),
+        }
+    }
+
+    // `set` and `define` create or overwrite a variable, while `set!` only
+    // changes one that is already defined.
+    fn parse_set(&mut self, keyword: &str, tokens: &mut TokenStream) -> ParserResult {
+        let usage = format!(
+            "`{}` must be followed by a variable name and the variable's new value",
+            keyword
+        );
+
+        if tokens.is_empty()? {
+            return Err(ParserError::ExpectedAnIdentifier(usage));
+        }
+
+        let variable_name = match tokens.next_token()? {
+            Token::Identifier(name) => name,
+            _ => return Err(ParserError::ExpectedAnIdentifier(usage)),
+        };
+
+        if tokens.is_empty()? {
+            return Err(ParserError::ExpectedAnExpression(usage));
+        }
+
+        let new_value = Box::new(self.parse_expression(tokens)?);
+
+        if keyword == "set!" {
+            Ok(Expression::Update(variable_name, new_value))
+        } else {
+            Ok(Expression::Set(variable_name, new_value))
+        }
+    }
+
+    fn parse_let(&mut self, tokens: &mut TokenStream) -> ParserResult {
+        let usage =
+            "`let` must be followed by a variable name, its value and an expression".to_string();
+
+        if tokens.is_empty()? {
+            return Err(ParserError::ExpectedAnIdentifier(usage));
+        }
+
+        let variable_name = match tokens.next_token()? {
+            Token::Identifier(name) => name,
+            _ => return Err(ParserError::ExpectedAnIdentifier(usage)),
+        };
+
+        if tokens.is_empty()? {
+            return Err(ParserError::ExpectedAnExpression(usage));
+        }
+
+        let value = self.parse_operand(tokens)?;
+
+        if tokens.is_empty()? {
+            return Err(ParserError::ExpectedAnExpression(usage));
+        }
+
+        let body = self.parse_expression(tokens)?;
+
+        Ok(Expression::Let(
+            variable_name,
+            Box::new(value),
+            Box::new(body),
+        ))
+    }
+
+    fn parse_for(&mut self, tokens: &mut TokenStream) -> ParserResult {
+        let usage = "`for` must be followed by a variable name, a list and a body".to_string();
+
+        if tokens.is_empty()? {
+            return Err(ParserError::ExpectedAnIdentifier(usage));
+        }
+
+        let variable_name = match tokens.next_token()? {
+            Token::Identifier(name) => name,
+            _ => return Err(ParserError::ExpectedAnIdentifier(usage)),
+        };
+
+        if tokens.is_empty()? {
+            return Err(ParserError::ExpectedAnExpression(usage));
+        }
+
+        let items = self.parse_operand(tokens)?;
+
+        if tokens.is_empty()? {
+            return Err(ParserError::ExpectedAnExpression(usage));
+        }
+
+        let body = self.parse_expression(tokens)?;
+
+        Ok(Expression::ForEach(
+            variable_name,
+            Box::new(items),
+            Box::new(body),
+        ))
+    }
+
+    fn parse_defined(&mut self, tokens: &mut TokenStream) -> ParserResult {
+        let usage = "`defined?` must be followed by a variable name".to_string();
+
+        if tokens.is_empty()? {
+            return Err(ParserError::ExpectedAnIdentifier(usage));
+        }
+
+        match tokens.next_token()? {
+            Token::Identifier(name) => Ok(Expression::Defined(name)),
+            _ => Err(ParserError::ExpectedAnIdentifier(usage)),
+        }
+    }
+
+    // Unlike `identity`, `quote` only accepts a name, and reads it as a plain
+    // variable even if it names a special form. This is how a function is
+    // passed as a value, as in `map quote inc [1 2]`, since its bare name
+    // would call it.
+    fn parse_quote(&mut self, tokens: &mut TokenStream) -> ParserResult {
+        let usage = "`quote` must be followed by a variable name".to_string();
+
+        if tokens.is_empty()? {
+            return Err(ParserError::ExpectedAnIdentifier(usage));
+        }
+
+        match tokens.next_token()? {
+            Token::Identifier(name) => Ok(Expression::Variable(name)),
+            _ => Err(ParserError::ExpectedAnIdentifier(usage)),
+        }
+    }
+
+    fn parse_def(&mut self, tokens: &mut TokenStream) -> ParserResult {
+        let usage =
+            "`def` must be followed by a function name, a list of parameter names and a body"
+                .to_string();
+
+        if tokens.is_empty()? {
+            return Err(ParserError::ExpectedAnIdentifier(usage));
+        }
+
+        let function_name = match tokens.next_token()? {
+            Token::Identifier(name) => name,
+            _ => return Err(ParserError::ExpectedAnIdentifier(usage)),
+        };
+
+        if tokens.is_empty()? || tokens.next_token()? != Token::LeftBracket {
+            return Err(ParserError::ExpectedAnIdentifier(usage));
+        }
+
+        let mut parameters = Vec::new();
+
+        loop {
+            if tokens.is_empty()? {
+                return Err(ParserError::UnterminatedList);
+            }
+
+            match tokens.next_token()? {
+                Token::RightBracket => break,
+                Token::Identifier(name) => parameters.push(name),
+                _ => return Err(ParserError::ExpectedAnIdentifier(usage)),
+            }
+        }
+
+        if tokens.is_empty()? {
+            return Err(ParserError::ExpectedAnExpression(usage));
+        }
+
+        let function = Rc::new(UserFunction {
+            parameters: parameters.clone(),
+            body: RefCell::new(Expression::Constant(Object::Noop)),
+        });
+
+        let object = Object::Function {
+            argument_count: Arity::Exact(parameters.len() as u16),
+            implementation: FunctionImplementation::User(function.clone()),
+        };
+
+        // The function is bound before its body is parsed so that it can call
+        // itself, through a weak reference to avoid a cycle. Its parameters
+        // shadow any outer bindings inside the body.
+        let recursive = Object::Function {
+            argument_count: Arity::Exact(parameters.len() as u16),
+            implementation: FunctionImplementation::Recursive(Rc::downgrade(&function)),
+        };
+
+        let previous = self
+            .environment
+            .insert(function_name.clone(), Rc::new(recursive));
+
+        let bindings = parameters
+            .into_iter()
+            .map(|name| (name, Object::Noop))
+            .collect();
+
+        match self.with_bindings(bindings, |us| us.parse_expression(tokens)) {
+            Ok(body) => {
+                *function.body.borrow_mut() = body;
+                self.environment
+                    .insert(function_name.clone(), Rc::new(object.clone()));
+                self.definitions.push((function_name.clone(), previous));
+
+                Ok(Expression::Set(
+                    function_name,
+                    Box::new(Expression::Constant(object)),
+                ))
+            }
+            Err(err) => {
+                match previous {
+                    Some(previous) => self.environment.insert(function_name, previous),
+                    None => self.environment.remove(&function_name),
+                };
+
+                Err(err)
+            }
+        }
+    }
+
+    fn parse_if(&mut self, tokens: &mut TokenStream) -> ParserResult {
+        if tokens.is_empty()? {
+            return Err(ParserError::ExpectedAnExpression(
+                "`if` must be followed by a condition".into(),
+            ));
+        }
+
+        let condition = Box::new(self.parse_expression(tokens)?);
+
+        if !self.check_keyword(tokens, "then")? {
+            return Err(ParserError::ExpectedThen);
+        }
+
+        if tokens.is_empty()? {
+            return Err(ParserError::ExpectedAnExpression(
+                "`then` must be followed by an expression".into(),
+            ));
+        }
+
+        let if_true = Box::new(self.parse_expression(tokens)?);
+
+        // There is no dedicated `else if`: the `else` branch is simply another
+        // `if`, so chains nest to the right and a trailing `else` belongs to
+        // the innermost `if` still missing one.
+        let otherwise = if self.check_keyword(tokens, "else")? {
+            if tokens.is_empty()? {
+                return Err(ParserError::ExpectedAnExpression(
+                    "`else` must be followed by an expression".into(),
+                ));
+            }
+
+            Some(Box::new(self.parse_expression(tokens)?))
+        } else {
+            None
+        };
+
+        Ok(Expression::If(condition, if_true, otherwise))
+    }
+
+    // `when` and `unless` are one-armed shorthands for `if`.
+    fn parse_when(&mut self, keyword: &str, tokens: &mut TokenStream) -> ParserResult {
+        let usage = format!("`{}` must be followed by a condition and a body", keyword);
+        let mut operands = Vec::new();
+
+        for index in 0..2 {
+            if self.is_argument_end(tokens)? {
+                return Err(ParserError::ExpectedAnExpression(usage));
+            }
+
+            let operand = if index == 0 {
+                self.parse_operand(tokens)?
+            } else {
+                self.parse_expression(tokens)?
+            };
+
+            operands.push(Box::new(operand));
+        }
+
+        let body = operands.pop().unwrap();
+        let condition = operands.pop().unwrap();
+
+        if keyword == "when" {
+            Ok(Expression::If(condition, body, None))
+        } else {
+            let noop = Box::new(Expression::Constant(Object::Noop));
+            Ok(Expression::If(condition, noop, Some(body)))
+        }
+    }
+
+    // `cond` takes pairs of conditions and results, optionally followed by an
+    // `else` result, and ends with `end`.
+    fn parse_cond(&mut self, tokens: &mut TokenStream) -> ParserResult {
+        let mut branches = Vec::new();
+
+        loop {
+            if tokens.is_empty()? {
+                return Err(ParserError::ExpectedAnIdentifier(
+                    "Expected keyword `end`".into(),
+                ));
+            }
+
+            if self.check_keyword(tokens, "end")? {
+                return Ok(Expression::Cond(branches, None));
+            }
+
+            if self.check_keyword(tokens, "else")? {
+                if tokens.is_empty()? {
+                    return Err(ParserError::ExpectedAnExpression(
+                        "`else` must be followed by an expression".into(),
+                    ));
+                }
+
+                let otherwise = Box::new(self.parse_expression(tokens)?);
+                self.expect_keyword(tokens, "end")?;
+
+                return Ok(Expression::Cond(branches, Some(otherwise)));
+            }
+
+            let condition = self.parse_operand(tokens)?;
+
+            if tokens.is_empty()? {
+                return Err(ParserError::ExpectedAnExpression(
+                    "Each `cond` condition must be followed by a result".into(),
+                ));
+            }
+
+            branches.push((condition, self.parse_operand(tokens)?));
+        }
+    }
+
+    fn parse_logical(&mut self, keyword: &str, tokens: &mut TokenStream) -> ParserResult {
+        let usage = format!("`{}` must be followed by two expressions", keyword);
+        let mut operands = Vec::new();
+
+        for index in 0..2 {
+            if self.is_argument_end(tokens)? {
+                return Err(ParserError::ExpectedAnExpression(usage));
+            }
+
+            let operand = if index == 0 {
+                self.parse_operand(tokens)?
+            } else {
+                self.parse_expression(tokens)?
+            };
+
+            operands.push(Box::new(operand));
+        }
+
+        let right = operands.pop().unwrap();
+        let left = operands.pop().unwrap();
+
+        if keyword == "and" {
+            Ok(Expression::And(left, right))
+        } else {
+            Ok(Expression::Or(left, right))
+        }
+    }
+
+    fn parse_while(&mut self, tokens: &mut TokenStream) -> ParserResult {
+        if tokens.is_empty()? {
+            return Err(ParserError::ExpectedAnExpression(
+                "`while` must be followed by a condition".into(),
+            ));
+        }
+
+        let condition = Box::new(self.parse_expression(tokens)?);
+
+        self.expect_keyword(tokens, "do")?;
+
+        if tokens.is_empty()? {
+            return Err(ParserError::ExpectedAnExpression(
+                "`do` must be followed by an expression".into(),
+            ));
+        }
+
+        let body = Box::new(self.parse_expression(tokens)?);
+
+        Ok(Expression::While(condition, body))
+    }
+
+    // `try body catch handler` runs `handler` if `body` fails, with the error
+    // message bound to `error-message`.
+    fn parse_try(&mut self, tokens: &mut TokenStream) -> ParserResult {
+        if self.is_argument_end(tokens)? {
+            return Err(ParserError::ExpectedAnExpression(
+                "`try` must be followed by an expression".into(),
+            ));
+        }
+
+        let body = Box::new(self.parse_expression(tokens)?);
+
+        self.expect_keyword(tokens, "catch")?;
+
+        if self.is_argument_end(tokens)? {
+            return Err(ParserError::ExpectedAnExpression(
+                "`catch` must be followed by an expression".into(),
+            ));
+        }
+
+        let handler = Box::new(self.parse_expression(tokens)?);
+
+        Ok(Expression::Try(body, handler))
+    }
+
+    fn parse_repeat(&mut self, tokens: &mut TokenStream) -> ParserResult {
+        let usage = "`repeat` must be followed by a count and a body";
+        let mut operands = Vec::new();
+
+        for index in 0..2 {
+            if self.is_argument_end(tokens)? {
+                return Err(ParserError::ExpectedAnExpression(usage.into()));
+            }
+
+            let operand = if index == 0 {
+                self.parse_operand(tokens)?
+            } else {
+                self.parse_expression(tokens)?
+            };
+
+            operands.push(Box::new(operand));
+        }
+
+        let body = operands.pop().unwrap();
+        let count = operands.pop().unwrap();
+
+        Ok(Expression::Repeat(count, body))
+    }
+
+    fn parse_expression(&mut self, tokens: &mut TokenStream) -> ParserResult {
+        match tokens.next_token()? {
+            Token::Identifier(name) => match name.as_str() {
+                "noop" => Ok(Expression::Constant(Object::Noop)),
+                "identity" => self.parse_identity(tokens),
+                "set" | "define" | "set!" => self.parse_set(&name, tokens),
+                "if" => self.parse_if(tokens),
+                "while" => self.parse_while(tokens),
+                "repeat" => self.parse_repeat(tokens),
+                "for" => self.parse_for(tokens),
+                "try" => self.parse_try(tokens),
+                "cond" => self.parse_cond(tokens),
+                "when" | "unless" => self.parse_when(&name, tokens),
+                "and" | "or" => self.parse_logical(&name, tokens),
+                "let" => self.parse_let(tokens),
+                "def" => self.parse_def(tokens),
+                "defined?" => self.parse_defined(tokens),
+                "quote" => self.parse_quote(tokens),
+                _ => self.parse_variable(&name, tokens),
+            },
+            Token::IntegerConstant(value) => Ok(Expression::Constant(Object::Integer(value))),
+            Token::FloatConstant(value) => Ok(Expression::Constant(Object::Float(value))),
+            Token::StringConstant(value) => Ok(Expression::Constant(Object::String(value.into()))),
+            Token::CharConstant(value) => Ok(Expression::Constant(Object::Char(value))),
+            Token::Boolean(value) => Ok(Expression::Constant(Object::Boolean(value))),
+            Token::LeftBrace => self.parse_compound(tokens),
+            Token::RightBrace => Err(ParserError::UnexpectedBrace),
+            Token::LeftBracket => self.parse_list(tokens),
+            Token::RightBracket => Err(ParserError::UnexpectedBracket),
+            Token::Terminator => Err(ParserError::UnexpectedTerminator),
+        }
+    }
+
+    // Parses every top-level expression without evaluating any of them.
+    // Function arities are looked up in the current environment, which is left
+    // unchanged afterwards.
+    pub fn parse(&mut self, input: &str) -> Result<Vec<Expression>, ParserError> {
+        let environment = self.environment.clone();
+        let definitions = self.definitions.len();
+        let mut tokens = TokenStream::new(input);
+        let mut expressions = Vec::new();
+
+        let result = loop {
+            if let Err(err) = self.skip_terminators(&mut tokens) {
+                break Err(err);
+            }
+
+            match tokens.is_empty() {
+                Ok(true) => break Ok(expressions),
+                Ok(false) => match self.parse_expression(&mut tokens) {
+                    Ok(expression) => expressions.push(expression),
+                    Err(err) => break Err(err),
+                },
+                Err(err) => break Err(err.into()),
+            }
+        };
+
+        self.environment = environment;
+        self.definitions.truncate(definitions);
+        result
+    }
+
+    pub fn eval_str(&mut self, input: &str) -> Result<Object, ParserError> {
+        self.eval(input)
+    }
+
+    pub fn eval<S: AsRef<str>>(&mut self, input: S) -> Result<Object, ParserError> {
+        self.eval_tokens(TokenStream::new(input.as_ref()))
+    }
+
+    // Like `eval`, but fails with `RuntimeError::Timeout` once `timeout` has
+    // passed. The deadline is only checked every so often, so evaluation may
+    // run slightly over.
+    pub fn eval_with_timeout<S: AsRef<str>>(
+        &mut self,
+        input: S,
+        timeout: Duration,
+    ) -> Result<Object, ParserError> {
+        self.deadline = Some(Instant::now() + timeout);
+        let result = self.eval(input);
+        self.deadline = None;
+        result
+    }
+
+    // Like `eval`, but returns the result of every top-level expression.
+    pub fn eval_all<S: AsRef<str>>(&mut self, input: S) -> Result<Vec<Object>, ParserError> {
+        let mut results = Vec::new();
+        self.eval_each(TokenStream::new(input.as_ref()), |result| {
+            results.push(result)
+        })?;
+        Ok(results)
+    }
+
+    // Evaluates a script as it is read, without loading all of it up front.
+    pub fn eval_reader<R: Read + 'static>(&mut self, source: R) -> Result<Object, ParserError> {
+        self.eval_tokens(TokenStream::from(Reader::from_reader(source)))
+    }
+
+    fn eval_tokens(&mut self, tokens: TokenStream) -> Result<Object, ParserError> {
+        let mut last = Object::Noop;
+        self.eval_each(tokens, |result| last = result)?;
+        Ok(last)
+    }
+
+    fn eval_each<F: FnMut(Object)>(
+        &mut self,
+        mut tokens: TokenStream,
+        mut on_result: F,
+    ) -> Result<(), ParserError> {
+        self.steps = 0;
+
+        loop {
+            self.skip_terminators(&mut tokens)?;
+
+            if tokens.is_empty()? {
+                break;
+            }
+
+            let mark = self.definitions.len();
+            let expression = self.parse_expression(&mut tokens);
+            self.forget_definitions(mark);
+
+            let result = expression?
+                .evaluate(self)
+                .map_err(ParserError::EvaluationFailed)?;
+
+            on_result(result);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(bloodbath.eval_str("noop"), Ok(Object::Noop),);
+        assert_eq!(bloodbath.eval_str("identity 1"), Ok(Object::Integer(1)));
+        assert_eq!(bloodbath.eval_str("+ 1 2"), Ok(Object::Integer(3)));
+        assert_eq!(bloodbath.eval_str("+ 1 + 1 1"), Ok(Object::Integer(3)));
+        assert_eq!(bloodbath.eval_str("+ + 1 1 1"), Ok(Object::Integer(3)));
+
+        assert_eq!(
+            bloodbath.eval_str("if 0 then 42 else 0"),
+            Ok(Object::Integer(42))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("if noop then 42 else 0"),
+            Ok(Object::Integer(0))
+        );
+
+        assert_eq!(bloodbath.eval_str("if noop then 42"), Ok(Object::Noop));
+
+        assert_eq!(
+            bloodbath.eval_str("if noop then 1 else if noop then 2 else 3"),
+            Ok(Object::Integer(3))
+        );
+
+        assert_eq!(bloodbath.eval_str("+ 1 2 3 4"), Ok(Object::Integer(10)));
+        assert_eq!(bloodbath.eval_str("* 2 3 4.0"), Ok(Object::Float(24.0)));
+        assert_eq!(
+            bloodbath.eval_str("+"),
+            Err(ParserError::NotEnoughArguments(
+                "+".into(),
+                Arity::AtLeast(2),
+                0
+            ))
+        );
+        assert_eq!(
+            bloodbath.eval_str("[+ 1 2 - 4 3]"),
+            Ok(Object::List(
+                vec![Object::Integer(3), Object::Integer(1)].into()
+            ))
+        );
+        assert_eq!(bloodbath.eval_str("- + 1 2 3"), Ok(Object::Integer(0)));
+
+        // A line break ends the optional arguments of a call, but not the
+        // arguments it needs.
+        assert_eq!(bloodbath.eval_str("+ 1 2\n* 3 4"), Ok(Object::Integer(12)));
+        assert_eq!(bloodbath.eval_str("+ 1\n  2 3"), Ok(Object::Integer(6)));
+        assert_eq!(
+            bloodbath.eval_str("{ set a + 1 2\n set b 4 }\na"),
+            Ok(Object::Integer(3))
+        );
+        assert_eq!(
+            bloodbath.eval_str("set a + 1 2\nset b 5\na"),
+            Ok(Object::Integer(3))
+        );
+        assert_eq!(
+            bloodbath.eval_str("set s 0\nfor i [1 2 3] set! s + s i\ns"),
+            Ok(Object::Integer(6))
+        );
+        assert_eq!(
+            bloodbath.eval_str("set r round 1.26 1\nlog 8 2\nr"),
+            Ok(Object::Float(1.3))
+        );
+        assert_eq!(
+            bloodbath.eval_str("format \"{}\" 1\n2"),
+            Ok(Object::Integer(2))
+        );
+        assert_eq!(bloodbath.eval_str("* + 1 2 3"), Ok(Object::Integer(9)));
+        assert_eq!(
+            bloodbath.eval_str("[round 1.75 round 1.25 1]"),
+            Ok(Object::List(
+                vec![Object::Float(2.0), Object::Float(1.0), Object::Integer(1)].into()
+            ))
+        );
+        assert_eq!(
+            bloodbath.eval_str("[{round 1.26 1}]"),
+            Ok(Object::List(vec![Object::Float(1.3)].into()))
+        );
+
+        assert_eq!(bloodbath.eval_str("{+ 1 2} + 3 4"), Ok(Object::Integer(7)));
+
+        assert_eq!(
+            bloodbath.eval_str("if + 1 2 then * 2 3 4 else 0"),
+            Ok(Object::Integer(24))
+        );
+
+        assert_eq!(bloodbath.eval_str("neg 5"), Ok(Object::Integer(-5)));
+        assert_eq!(bloodbath.eval_str("neg -2.5"), Ok(Object::Float(2.5)));
+        assert_eq!(bloodbath.eval_str("neg + 1 2"), Ok(Object::Integer(-3)));
+        assert!(bloodbath.eval_str("neg noop").is_err());
+
+        assert_eq!(bloodbath.eval_str("max 1 2.5"), Ok(Object::Float(2.5)));
+        assert_eq!(bloodbath.eval_str("band 12 10"), Ok(Object::Integer(8)));
+        assert_eq!(bloodbath.eval_str("shl 1 4"), Ok(Object::Integer(16)));
+        assert!(bloodbath.eval_str("bor 1.5 1").is_err());
+        assert_eq!(bloodbath.eval_str("min 4 max 1 2"), Ok(Object::Integer(2)));
+    }
+
+    #[test]
+    fn test_variables() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(bloodbath.eval_str("set a 10"), Ok(Object::Integer(10)));
+        assert_eq!(bloodbath.eval_str("set b 20"), Ok(Object::Integer(20)));
+        assert_eq!(bloodbath.eval_str("set c + a b"), Ok(Object::Integer(30)));
+        assert_eq!(bloodbath.eval_str("set + c"), Ok(Object::Integer(30)));
+        assert_eq!(bloodbath.eval_str("+"), Ok(Object::Integer(30)));
+        assert_eq!(bloodbath.eval_str("identity +"), Ok(Object::Integer(30)));
+    }
+
+    #[test]
+    fn test_compound() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(bloodbath.eval_str("{1 2 3}"), Ok(Object::Integer(3)));
+        assert_eq!(bloodbath.eval_str("{set a 5}"), Ok(Object::Integer(5)));
+        assert_eq!(bloodbath.eval_str("{set b + 5 a}"), Ok(Object::Integer(10)));
+
+        assert_eq!(
+            bloodbath.eval_str("if {a noop} then 1 else 0"),
             Ok(Object::Integer(0))
         );
     }
+
+    #[test]
+    fn test_lists() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(bloodbath.eval_str("[]"), Ok(Object::List(vec![].into())));
+
+        assert_eq!(
+            bloodbath.eval_str("[1 + 1 1 [2.5]]"),
+            Ok(Object::List(
+                vec![
+                    Object::Integer(1),
+                    Object::Integer(2),
+                    Object::List(vec![Object::Float(2.5)].into()),
+                ]
+                .into()
+            ))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("[1 2"),
+            Err(ParserError::UnterminatedList)
+        );
+
+        assert_eq!(bloodbath.eval_str("]"), Err(ParserError::UnexpectedBracket));
+    }
+
+    #[test]
+    fn test_clock() {
+        struct FakeClock;
+
+        impl Clock for FakeClock {
+            fn time(&self) -> f64 {
+                1000.5
+            }
+
+            fn clock(&self) -> f64 {
+                42.0
+            }
+        }
+
+        let mut bloodbath = Bloodbath::new().with_clock(FakeClock);
+
+        assert_eq!(bloodbath.eval_str("time"), Ok(Object::Float(1000.5)));
+        assert_eq!(bloodbath.eval_str("clock"), Ok(Object::Float(42.0)));
+        assert_eq!(bloodbath.eval_str("- clock clock"), Ok(Object::Float(0.0)));
+    }
+
+    #[test]
+    fn test_shuffle() {
+        let mut bloodbath = Bloodbath::new();
+
+        bloodbath.seed(1337);
+        let first = bloodbath.eval_str("shuffle [1 2 3 4 5 6 7 8]").unwrap();
+
+        bloodbath.seed(1337);
+        let second = bloodbath.eval_str("shuffle [1 2 3 4 5 6 7 8]").unwrap();
+
+        assert_eq!(first, second);
+
+        let mut items: Vec<i64> = first
+            .get_list()
+            .unwrap()
+            .iter()
+            .map(|x| x.get_integer().unwrap())
+            .collect();
+
+        items.sort();
+        assert_eq!(items, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+
+        assert!(bloodbath.eval_str("shuffle 5").is_err());
+    }
+
+    #[test]
+    fn test_rand() {
+        let mut bloodbath = Bloodbath::new();
+        let draw = "[rand rand-int 1 6 rand rand-int -10 10]";
+
+        bloodbath.seed(99);
+        let first = bloodbath.eval_str(draw).unwrap();
+
+        bloodbath.seed(99);
+        assert_eq!(bloodbath.eval_str(draw), Ok(first.clone()));
+
+        let items = first.get_list().unwrap();
+
+        assert!((0.0..1.0).contains(&items[0].get_float().unwrap()));
+        assert!((1..=6).contains(&items[1].get_integer().unwrap()));
+        assert!((-10..=10).contains(&items[3].get_integer().unwrap()));
+
+        assert_eq!(bloodbath.eval_str("rand-int 3 3"), Ok(Object::Integer(3)));
+        assert!(bloodbath.eval_str("rand-int 3 2").is_err());
+        assert!(bloodbath.eval_str("rand-int 1 2.5").is_err());
+    }
+
+    #[test]
+    fn test_sample() {
+        let mut bloodbath = Bloodbath::new();
+
+        bloodbath.seed(42);
+        let first = bloodbath.eval_str("sample 3 [1 2 3 4 5]").unwrap();
+
+        bloodbath.seed(42);
+        let second = bloodbath.eval_str("sample 3 [1 2 3 4 5]").unwrap();
+
+        assert_eq!(first, second);
+
+        let mut items: Vec<i64> = first
+            .get_list()
+            .unwrap()
+            .iter()
+            .map(|x| x.get_integer().unwrap())
+            .collect();
+
+        assert_eq!(items.len(), 3);
+
+        items.sort();
+        items.dedup();
+        assert_eq!(items.len(), 3);
+
+        assert!(bloodbath.eval_str("sample 6 [1 2 3 4 5]").is_err());
+        assert!(bloodbath.eval_str("sample 1 5").is_err());
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let flag = Arc::new(AtomicBool::new(true));
+        let mut bloodbath = Bloodbath::new().with_cancellation_flag(flag.clone());
+
+        assert_eq!(
+            bloodbath.eval_str("+ 1 2"),
+            Err(ParserError::EvaluationFailed(RuntimeError::Cancelled))
+        );
+
+        flag.store(false, Ordering::Relaxed);
+        assert_eq!(bloodbath.eval_str("+ 1 2"), Ok(Object::Integer(3)));
+
+        let trigger = flag.clone();
+        bloodbath.register("cancel", Arity::Exact(0), move |_| {
+            trigger.store(true, Ordering::Relaxed);
+            Ok(Object::Noop)
+        });
+
+        assert_eq!(
+            bloodbath.eval_str("{set a 1 cancel set a 2}"),
+            Err(ParserError::EvaluationFailed(RuntimeError::Cancelled))
+        );
+
+        flag.store(false, Ordering::Relaxed);
+        assert_eq!(bloodbath.eval_str("a"), Ok(Object::Integer(1)));
+    }
+
+    #[test]
+    fn test_strings() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(
+            bloodbath.eval_str(r#"concat "foo" "bar""#),
+            Ok(Object::String("foobar".into()))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str(r#"length "hello""#),
+            Ok(Object::Integer(5))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str(r#"length "héllo""#),
+            Ok(Object::Integer(5))
+        );
+        assert_eq!(bloodbath.eval_str("length [1 2 3]"), Ok(Object::Integer(3)));
+
+        assert_eq!(
+            bloodbath.eval_str(r#"substring "hello" 1 3"#),
+            Ok(Object::String("el".into()))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str(r#"substring "hello" 0 5"#),
+            Ok(Object::String("hello".into()))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str(r#"substring "hello" 3 6"#),
+            Err(ParserError::EvaluationFailed(
+                RuntimeError::InvalidArgument(
+                    "Substring 3..6 is out of range for a string of length 5".into()
+                )
+            ))
+        );
+
+        assert!(bloodbath.eval_str(r#"substring "hello" 3 1"#).is_err());
+        assert!(bloodbath.eval_str(r#"concat "foo" 1"#).is_err());
+
+        assert_eq!(
+            bloodbath.eval_str(r#"join ", " ["a" "b" "c"]"#),
+            Ok(Object::String("a, b, c".into()))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str(r#"length split "," "a,b,c,""#),
+            Ok(Object::Integer(4))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str(r#"upper trim "  shout  ""#),
+            Ok(Object::String("SHOUT".into()))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str(r#"set a 1 format "a = {}, a + 1 = {}" a + a 1"#),
+            Ok(Object::String("a = 1, a + 1 = 2".into()))
+        );
+    }
+
+    #[test]
+    fn test_read_line() {
+        let input = std::io::Cursor::new("first line\nsecond line\r\n");
+        let mut bloodbath = Bloodbath::new().with_input(input);
+
+        assert_eq!(
+            bloodbath.eval_str("read-line"),
+            Ok(Object::String("first line".into()))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("concat read-line \"!\""),
+            Ok(Object::String("second line!".into()))
+        );
+
+        assert_eq!(bloodbath.eval_str("read-line"), Ok(Object::Noop));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(
+            bloodbath.eval_str(r#"error "boom""#),
+            Ok(Object::Error("boom".into()))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str(r#"{set a 1 error "boom" set a 2}"#),
+            Ok(Object::Error("boom".into()))
+        );
+
+        assert_eq!(bloodbath.eval_str("a"), Ok(Object::Integer(1)));
+
+        assert_eq!(
+            bloodbath.eval_str(r#"+ 1 - 2 error "nested""#),
+            Ok(Object::Error("nested".into()))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str(r#"set a [1 error "in a list"]"#),
+            Ok(Object::Error("in a list".into()))
+        );
+
+        assert_eq!(bloodbath.eval_str("a"), Ok(Object::Integer(1)));
+
+        assert_eq!(
+            bloodbath.eval_str(r#"if error "condition" then 1 else 2"#),
+            Ok(Object::Error("condition".into()))
+        );
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut bloodbath = Bloodbath::new();
+        bloodbath.eval_str("set x 1").unwrap();
+
+        let snapshot = bloodbath.snapshot();
+        bloodbath.eval_str("set x 2 set y 3 set + quote -").unwrap();
+        assert_eq!(bloodbath.eval_str("x"), Ok(Object::Integer(2)));
+
+        bloodbath.restore(snapshot.clone());
+        assert_eq!(bloodbath.eval_str("x"), Ok(Object::Integer(1)));
+        assert_eq!(bloodbath.eval_str("+ 1 2"), Ok(Object::Integer(3)));
+        assert!(!bloodbath.is_defined("y"));
+
+        bloodbath.eval_str("set x 4").unwrap();
+        bloodbath.restore(snapshot);
+        assert_eq!(bloodbath.eval_str("x"), Ok(Object::Integer(1)));
+    }
+
+    #[test]
+    fn test_clone() {
+        let mut original = Bloodbath::new();
+        original.eval_str("set x 1 def f [] x").unwrap();
+
+        let mut snapshot = original.clone();
+        snapshot.eval_str("set x 2 set y 3 def f [] 4").unwrap();
+
+        assert_eq!(snapshot.eval_str("+ x y"), Ok(Object::Integer(5)));
+        assert_eq!(snapshot.eval_str("f"), Ok(Object::Integer(4)));
+
+        assert_eq!(original.eval_str("x"), Ok(Object::Integer(1)));
+        assert_eq!(original.eval_str("f"), Ok(Object::Integer(1)));
+        assert!(!original.is_defined("y"));
+    }
+
+    #[test]
+    fn test_call_function() {
+        let mut bloodbath = Bloodbath::new();
+        bloodbath.eval_str("def inc [x] + x 1").unwrap();
+
+        assert_eq!(
+            bloodbath.call_function("inc", vec![Object::Integer(41)]),
+            Ok(Object::Integer(42))
+        );
+
+        assert_eq!(
+            bloodbath.call_function("+", vec![Object::Integer(1), Object::Integer(2)]),
+            Ok(Object::Integer(3))
+        );
+
+        assert_eq!(
+            bloodbath.call_function("inc", vec![]),
+            Err(RuntimeError::InvalidArgument(
+                "`inc` expects 1 argument, got 0".into()
+            ))
+        );
+
+        assert_eq!(
+            bloodbath.call_function("nope", vec![]),
+            Err(RuntimeError::UndefinedVariable("nope".into()))
+        );
+
+        bloodbath.eval_str("set x 1").unwrap();
+
+        assert_eq!(
+            bloodbath.call_function("x", vec![]),
+            Err(RuntimeError::InvalidArgument(
+                "`x` is not a function".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_else_if_chain() {
+        let mut bloodbath = Bloodbath::new();
+
+        let chain = "if = x 1 then \"one\" else if = x 2 then \"two\" \
+                     else if = x 3 then \"three\" else \"many\"";
+
+        for (x, expected) in [(1, "one"), (2, "two"), (3, "three"), (4, "many")] {
+            bloodbath.eval_str(&format!("set x {}", x)).unwrap();
+            assert_eq!(
+                bloodbath.eval_str(chain),
+                Ok(Object::String(expected.into()))
+            );
+        }
+
+        assert_eq!(
+            bloodbath.eval_str("if false then 1 else if false then 2"),
+            Ok(Object::Noop)
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("if false then if true then 1 else 2"),
+            Ok(Object::Noop)
+        );
+    }
+
+    #[test]
+    fn test_missing_then() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(
+            bloodbath.eval_str("if true 1 else 2"),
+            Err(ParserError::ExpectedThen)
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("if true"),
+            Err(ParserError::ExpectedThen)
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("if {set a 1 true} 1 else 2"),
+            Err(ParserError::ExpectedThen)
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("if {set a 1 = a 1} then 1 else 2"),
+            Ok(Object::Integer(1))
+        );
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        let mut bloodbath = Bloodbath::new();
+
+        let error = Err(ParserError::EvaluationFailed(
+            RuntimeError::InvalidArgument("`-` expects two numbers".into()),
+        ));
+
+        assert_eq!(bloodbath.eval_str("- 1 [2]"), error);
+        assert_eq!(bloodbath.eval_str("+ 1 * 2 {3 - 4 [5]}"), error);
+        assert_eq!(bloodbath.eval_str("if - noop 1 then 1 else 2"), error);
+
+        assert_eq!(
+            bloodbath.eval_str("{set a 1 set a - a noop set a 2}"),
+            error
+        );
+
+        assert_eq!(bloodbath.eval_str("a"), Ok(Object::Integer(1)));
+
+        let undefined = Err(ParserError::EvaluationFailed(
+            RuntimeError::UndefinedVariable("nosuch".into()),
+        ));
+
+        assert_eq!(bloodbath.eval_str("nosuch"), undefined);
+        assert_eq!(bloodbath.eval_str("+ 1 * 2 {3 - 4 [nosuch]}"), undefined);
+        assert_eq!(bloodbath.eval_str("neg nosuch"), undefined);
+        assert_eq!(bloodbath.eval_str("def f [x] - x nosuch f 1"), undefined);
+        assert_eq!(bloodbath.eval_str("{set a 2 nosuch set a 3}"), undefined);
+
+        assert_eq!(bloodbath.eval_str("a"), Ok(Object::Integer(2)));
+        assert!(!bloodbath.is_defined("nosuch"));
+    }
+
+    #[test]
+    fn test_let() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(bloodbath.eval_str("let x 5 + x x"), Ok(Object::Integer(10)));
+        assert_eq!(
+            bloodbath.eval_str("x"),
+            Err(ParserError::EvaluationFailed(
+                RuntimeError::UndefinedVariable("x".into())
+            ))
+        );
+
+        assert_eq!(bloodbath.eval_str("set y 1"), Ok(Object::Integer(1)));
+        assert_eq!(bloodbath.eval_str("let y 5 * y 2"), Ok(Object::Integer(10)));
+        assert_eq!(bloodbath.eval_str("y"), Ok(Object::Integer(1)));
+
+        assert_eq!(
+            bloodbath.eval_str("let a 1 let b 2 set y + y a b"),
+            Ok(Object::Integer(4))
+        );
+
+        assert!(!bloodbath.is_defined("a"));
+        assert!(!bloodbath.is_defined("b"));
+        assert_eq!(bloodbath.eval_str("y"), Ok(Object::Integer(4)));
+
+        assert!(bloodbath.eval_str("let y 2 - y noop").is_err());
+        assert_eq!(bloodbath.eval_str("y"), Ok(Object::Integer(4)));
+
+        assert_eq!(
+            bloodbath.eval_str("let y 2"),
+            Err(ParserError::ExpectedAnExpression(
+                "`let` must be followed by a variable name, its value and an expression".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_argument_count() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(
+            bloodbath.eval_str("- 1"),
+            Err(ParserError::NotEnoughArguments(
+                "-".into(),
+                Arity::Exact(2),
+                1
+            ))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("{- 1}"),
+            Err(ParserError::NotEnoughArguments(
+                "-".into(),
+                Arity::Exact(2),
+                1
+            ))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("[neg]"),
+            Err(ParserError::NotEnoughArguments(
+                "neg".into(),
+                Arity::Exact(1),
+                0
+            ))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("format"),
+            Err(ParserError::NotEnoughArguments(
+                "format".into(),
+                Arity::AtLeast(1),
+                0
+            ))
+        );
+
+        // Extra tokens after a complete call are just the next expression.
+        assert_eq!(bloodbath.eval_str("- 1 2 3"), Ok(Object::Integer(3)));
+        assert_eq!(
+            bloodbath.eval_str("[- 1 2 3]"),
+            bloodbath.eval_str("[-1 3]")
+        );
+    }
+
+    #[test]
+    fn test_define_and_update() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(
+            bloodbath.eval_str("set! x 1"),
+            Err(ParserError::EvaluationFailed(
+                RuntimeError::UndefinedVariable("x".into())
+            ))
+        );
+
+        assert!(!bloodbath.is_defined("x"));
+
+        assert_eq!(bloodbath.eval_str("define x 1"), Ok(Object::Integer(1)));
+        assert_eq!(bloodbath.eval_str("set! x 2"), Ok(Object::Integer(2)));
+        assert_eq!(bloodbath.eval_str("x"), Ok(Object::Integer(2)));
+
+        // Errors are passed through without assigning them.
+        assert_eq!(
+            bloodbath.eval_str(r#"set! x error "no""#),
+            Ok(Object::Error("no".into()))
+        );
+
+        assert_eq!(bloodbath.eval_str("x"), Ok(Object::Integer(2)));
+
+        assert_eq!(
+            bloodbath.eval_str("set! 1 2"),
+            Err(ParserError::ExpectedAnIdentifier(
+                "`set!` must be followed by a variable name and the variable's new value".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_restore_builtin() {
+        let mut bloodbath = Bloodbath::new();
+
+        bloodbath.eval_str("set + 5").unwrap();
+        assert_eq!(bloodbath.eval_str("+"), Ok(Object::Integer(5)));
+
+        assert!(bloodbath.restore_builtin("+"));
+        assert_eq!(bloodbath.eval_str("+ 1 2"), Ok(Object::Integer(3)));
+
+        bloodbath.eval_str("set pi 3").unwrap();
+        assert!(bloodbath.restore_builtin("pi"));
+        assert_eq!(
+            bloodbath.eval_str("pi"),
+            Ok(Object::Float(std::f64::consts::PI))
+        );
+
+        bloodbath.eval_str("set mine 1").unwrap();
+        assert!(!bloodbath.restore_builtin("mine"));
+        assert_eq!(bloodbath.eval_str("mine"), Ok(Object::Integer(1)));
+    }
+
+    #[test]
+    fn test_gensym() {
+        let mut bloodbath = Bloodbath::new();
+
+        let first = bloodbath.eval_str("gensym").unwrap();
+        let second = bloodbath.eval_str("gensym").unwrap();
+
+        assert_eq!(first, Object::String("g#1".into()));
+        assert_eq!(second, Object::String("g#2".into()));
+        assert_ne!(first, second);
+
+        bloodbath.seed(1);
+        assert_eq!(bloodbath.eval_str("gensym"), Ok(first.clone()));
+
+        bloodbath.eval_str("gensym").unwrap();
+        bloodbath.reset();
+        assert_eq!(bloodbath.eval_str("gensym"), Ok(first));
+    }
+
+    #[test]
+    fn test_prelude() {
+        let mut empty = Bloodbath::empty();
+        assert!(!empty.is_defined("+"));
+        assert!(empty.list_bindings().is_empty());
+        assert_eq!(empty.describe("+"), None);
+
+        empty.register("+", Arity::Variadic, crate::builtins::add);
+        assert_eq!(empty.eval_str("+ 1 2"), Ok(Object::Integer(3)));
+        assert!(!empty.restore_builtin("-"));
+
+        empty.reset();
+        assert!(!empty.is_defined("+"));
+
+        let mut bloodbath = Bloodbath::with_prelude();
+        assert!(bloodbath.is_defined("+"));
+        assert_eq!(bloodbath.eval_str("+ 1 2"), Ok(Object::Integer(3)));
+        assert_eq!(
+            bloodbath.list_bindings().len(),
+            Bloodbath::new().list_bindings().len()
+        );
+    }
+
+    #[test]
+    fn test_sandboxed() {
+        let output = SharedOutput::default();
+        let mut bloodbath = Bloodbath::sandboxed().with_output(output.clone());
+
+        assert_eq!(bloodbath.eval_str("+ 1 2"), Ok(Object::Integer(3)));
+        assert_eq!(
+            bloodbath.eval_str("map quote neg [1]"),
+            bloodbath.eval_str("[-1]")
+        );
+
+        for name in IMPURE_BUILTINS {
+            assert!(!bloodbath.is_defined(name), "{}", name);
+        }
+
+        assert!(bloodbath.eval_str(r#"call quote print ["x"]"#).is_err());
+        assert!(output.0.borrow().is_empty());
+
+        assert!(!bloodbath.restore_builtin("print"));
+        bloodbath.reset();
+        assert!(!bloodbath.is_defined("print"));
+        assert!(bloodbath.is_defined("+"));
+
+        for name in IMPURE_BUILTINS {
+            assert!(Bloodbath::new().is_defined(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut bloodbath = Bloodbath::new();
+
+        bloodbath.eval_str("set x 1 set + 2").unwrap();
+        bloodbath.reset();
+
+        assert!(!bloodbath.is_defined("x"));
+        assert_eq!(bloodbath.eval_str("+ 1 2"), Ok(Object::Integer(3)));
+        assert!(bloodbath.describe("+").is_some());
+
+        bloodbath.seed(1);
+        let first = bloodbath.eval_str("shuffle [1 2 3 4 5 6 7 8]");
+        bloodbath.seed(1);
+        assert_eq!(bloodbath.eval_str("shuffle [1 2 3 4 5 6 7 8]"), first);
+    }
+
+    #[test]
+    fn test_list_bindings() {
+        let mut bloodbath = Bloodbath::new();
+
+        bloodbath.eval_str("set a 10 set b [1.5]").unwrap();
+
+        let bindings = bloodbath.list_bindings();
+        let find = |name: &str| {
+            bindings
+                .iter()
+                .find(|(binding, _)| binding == name)
+                .map(|(_, description)| description.as_str())
+        };
+
+        assert_eq!(find("a"), Some("Integer(10)"));
+        assert_eq!(find("b"), Some("List([Float(1.5)])"));
+        assert_eq!(find("-"), Some("<builtin/2>"));
+        assert_eq!(find("+"), Some("<builtin/2+>"));
+        assert_eq!(find("c"), None);
+
+        let mut names: Vec<&String> = bindings.iter().map(|(name, _)| name).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            bindings.iter().map(|(name, _)| name).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_booleans() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(bloodbath.eval_str("true"), Ok(Object::Boolean(true)));
+        assert_eq!(bloodbath.eval_str("false"), Ok(Object::Boolean(false)));
+
+        assert_eq!(
+            bloodbath.eval_str("if true then 1 else 2"),
+            Ok(Object::Integer(1))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("if false then 1 else 2"),
+            Ok(Object::Integer(2))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("identity true"),
+            Ok(Object::Boolean(true))
+        );
+        assert!(bloodbath.eval_str("set true 5").is_err());
+        assert!(bloodbath.eval_str("let false 5 false").is_err());
+    }
+
+    #[test]
+    fn test_defined() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(
+            bloodbath.eval_str("defined? unset"),
+            Ok(Object::Boolean(false))
+        );
+
+        assert!(!bloodbath.is_defined("unset"));
+
+        assert_eq!(bloodbath.eval_str("set unset 1"), Ok(Object::Integer(1)));
+        assert_eq!(
+            bloodbath.eval_str("defined? unset"),
+            Ok(Object::Boolean(true))
+        );
+        assert_eq!(bloodbath.eval_str("defined? +"), Ok(Object::Boolean(true)));
+
+        assert_eq!(
+            bloodbath.eval_str("{defined? later set later 1 defined? later}"),
+            Ok(Object::Boolean(true))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("if defined? nothing then 1 else 2"),
+            Ok(Object::Integer(2))
+        );
+
+        assert!(bloodbath.eval_str("defined? 1").is_err());
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_trace() {
+        let output = SharedOutput::default();
+        let mut bloodbath = Bloodbath::new().with_trace_output(output.clone());
+
+        bloodbath.eval_str("+ 1 2").unwrap();
+        assert!(output.0.borrow().is_empty());
+
+        bloodbath.set_trace(true);
+        assert!(bloodbath.is_tracing());
+        bloodbath.eval_str("+ 1 2").unwrap();
+
+        let trace = String::from_utf8(output.0.borrow().clone()).unwrap();
+        let lines: Vec<&str> = trace.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "Constant(Integer(1)) => Integer(1)");
+        assert!(lines[2].starts_with("FunctionCall(<builtin>"));
+        assert!(lines[2].ends_with("=> Integer(3)"));
+
+        output.0.borrow_mut().clear();
+        bloodbath.eval_str("def f [x] x f 1").unwrap();
+
+        let trace = String::from_utf8(output.0.borrow().clone()).unwrap();
+        assert!(trace.contains("  Variable(\"x\") => Integer(1)"));
+
+        output.0.borrow_mut().clear();
+        bloodbath.set_trace(false);
+        bloodbath.eval_str("+ 1 2").unwrap();
+        assert!(output.0.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_print() {
+        let output = SharedOutput::default();
+        let mut bloodbath = Bloodbath::new().with_output(output.clone());
+
+        assert_eq!(
+            bloodbath.eval_str(r#"{print "hello" print [1 2.5 "x"] print noop}"#),
+            Ok(Object::Noop)
+        );
+
+        assert_eq!(
+            String::from_utf8(output.0.borrow().clone()).unwrap(),
+            "hello\n[1 2.5 x]\n\n"
+        );
+    }
+
+    #[test]
+    fn test_comparisons() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(bloodbath.eval_str("< 1 2"), Ok(Object::Boolean(true)));
+        assert_eq!(bloodbath.eval_str("< 2 1.5"), Ok(Object::Boolean(false)));
+        assert_eq!(bloodbath.eval_str("> 2 1.5"), Ok(Object::Boolean(true)));
+        assert_eq!(bloodbath.eval_str("<= 2 2.0"), Ok(Object::Boolean(true)));
+        assert_eq!(bloodbath.eval_str(">= 1 2"), Ok(Object::Boolean(false)));
+        assert_eq!(bloodbath.eval_str("= 2 2.0"), Ok(Object::Boolean(true)));
+        assert_eq!(bloodbath.eval_str("= 2 3"), Ok(Object::Boolean(false)));
+        assert!(bloodbath.eval_str(r#"< 1 "2""#).is_err());
+    }
+
+    #[test]
+    fn test_special_floats() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(bloodbath.eval_str("= nan nan"), Ok(Object::Boolean(true)));
+        assert_eq!(bloodbath.eval_str("< nan 1"), Ok(Object::Boolean(false)));
+        assert_eq!(
+            bloodbath.eval_str("infinite? / 1.0 0.0"),
+            Ok(Object::Boolean(true))
+        );
+        assert_eq!(
+            bloodbath.eval_str("infinite? / -1 0.0"),
+            Ok(Object::Boolean(true))
+        );
+        assert_eq!(
+            bloodbath.eval_str("nan? / 0.0 0.0"),
+            Ok(Object::Boolean(true))
+        );
+        assert_eq!(bloodbath.eval_str("nan? inf"), Ok(Object::Boolean(false)));
+        assert_eq!(
+            bloodbath.eval_str("= inf / 1.0 0.0"),
+            Ok(Object::Boolean(true))
+        );
+
+        assert_eq!(bloodbath.eval_str("= nan nan"), Ok(Object::Boolean(true)));
+        assert_eq!(bloodbath.eval_str("eq? nan nan"), Ok(Object::Boolean(true)));
+        assert_eq!(
+            bloodbath.eval_str("[nan? min nan 1 nan? max 1 nan]"),
+            bloodbath.eval_str("[true true]")
+        );
+    }
+
+    #[test]
+    fn test_quote() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert!(matches!(
+            bloodbath.eval_str("quote +"),
+            Ok(Object::Function {
+                argument_count: Arity::AtLeast(2),
+                ..
+            })
+        ));
+
+        assert_eq!(
+            bloodbath.eval_str("length [quote + 1 2]"),
+            Ok(Object::Integer(3))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("eq? quote - identity -"),
+            Ok(Object::Boolean(true))
+        );
+        assert_eq!(
+            bloodbath.eval_str("quote if"),
+            Err(ParserError::EvaluationFailed(
+                RuntimeError::UndefinedVariable("if".into())
+            ))
+        );
+
+        assert!(bloodbath.eval_str("quote 1").is_err());
+        assert!(bloodbath.eval_str("quote").is_err());
+    }
+
+    #[test]
+    fn test_call() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(
+            bloodbath.eval_str("[call quote + [1 2] call quote * [3 4]]"),
+            Ok(Object::List(
+                vec![Object::Integer(3), Object::Integer(12)].into()
+            ))
+        );
+
+        // A bare name calls the function rather than passing it.
+        assert!(bloodbath.eval_str("call + [1 2]").is_err());
+
+        assert_eq!(
+            bloodbath.eval_str("call quote + [1 2]"),
+            Ok(Object::Integer(3))
+        );
+        assert_eq!(
+            bloodbath.eval_str("call quote + [1 2 3]"),
+            Ok(Object::Integer(6))
+        );
+        assert_eq!(
+            bloodbath.eval_str("call quote - [5 2]"),
+            Ok(Object::Integer(3))
+        );
+
+        bloodbath.eval_str("def twice [x] * x 2").unwrap();
+        assert_eq!(
+            bloodbath.eval_str("call quote twice [4]"),
+            Ok(Object::Integer(8))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("call quote - [1]"),
+            Err(ParserError::EvaluationFailed(
+                RuntimeError::InvalidArgument(
+                    "`call` got 1 arguments for a function that takes 2".into()
+                )
+            ))
+        );
+
+        assert!(bloodbath.eval_str(r#"call quote format []"#).is_err());
+        assert!(bloodbath.eval_str("call 1 [1]").is_err());
+        assert!(bloodbath.eval_str("call quote - 1").is_err());
+    }
+
+    #[test]
+    fn test_arity() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(
+            bloodbath.eval_str("[arity quote + arity quote round]"),
+            Ok(Object::List(
+                vec![Object::Integer(2), Object::Integer(1)].into()
+            ))
+        );
+        assert_eq!(bloodbath.eval_str("arity quote -"), Ok(Object::Integer(2)));
+        assert_eq!(
+            bloodbath.eval_str("arity quote neg"),
+            Ok(Object::Integer(1))
+        );
+        assert_eq!(bloodbath.eval_str("arity quote +"), Ok(Object::Integer(2)));
+        assert_eq!(
+            bloodbath.eval_str("arity quote format"),
+            Ok(Object::Integer(1))
+        );
+
+        bloodbath.eval_str("def add3 [a b c] + a b c").unwrap();
+        assert_eq!(
+            bloodbath.eval_str("arity quote add3"),
+            Ok(Object::Integer(3))
+        );
+
+        assert!(bloodbath.eval_str("arity 5").is_err());
+    }
+
+    #[test]
+    fn test_higher_order() {
+        let mut bloodbath = Bloodbath::new();
+
+        bloodbath.eval_str("def inc [x] + x 1").unwrap();
+        bloodbath.eval_str("def even? [x] = 0 band x 1").unwrap();
+
+        assert_eq!(
+            bloodbath.eval_str("map quote inc [1 2 3]"),
+            bloodbath.eval_str("[2 3 4]")
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("filter quote even? [1 2 3 4]"),
+            bloodbath.eval_str("[2 4]")
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("reduce quote + 0 [1 2 3]"),
+            Ok(Object::Integer(6))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("reduce quote - 10 [1 2 3]"),
+            Ok(Object::Integer(4))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("[map quote inc [1] reduce quote * 1 [2 3]]"),
+            bloodbath.eval_str("[[2] 6]")
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("map quote inc []"),
+            Ok(Object::List(vec![].into()))
+        );
+        assert_eq!(
+            bloodbath.eval_str("reduce quote + 5 []"),
+            Ok(Object::Integer(5))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str(r#"def fail [x] error "nope" map quote fail [1]"#),
+            Ok(Object::Error("nope".into()))
+        );
+
+        assert!(bloodbath.eval_str("map quote - [1]").is_err());
+        assert!(bloodbath.eval_str("map quote inc 1").is_err());
+        assert!(bloodbath.eval_str("reduce quote inc 0 [1]").is_err());
+    }
+
+    #[test]
+    fn test_structural_equality() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(
+            bloodbath.eval_str("eq? [1 \"a\"] [1 \"a\"]"),
+            Ok(Object::Boolean(true))
+        );
+        assert_eq!(
+            bloodbath.eval_str("eq? identity - identity -"),
+            Ok(Object::Boolean(true))
+        );
+        assert_eq!(
+            bloodbath.eval_str("eq? identity - identity neg"),
+            Ok(Object::Boolean(false))
+        );
+        assert_eq!(
+            bloodbath.eval_str("ne? identity - identity neg"),
+            Ok(Object::Boolean(true))
+        );
+
+        bloodbath.eval_str("set minus identity -").unwrap();
+        assert_eq!(
+            bloodbath.eval_str("eq? identity minus identity -"),
+            Ok(Object::Boolean(true))
+        );
+    }
+
+    #[test]
+    fn test_def() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert!(matches!(
+            bloodbath.eval_str("def inc [x] + x 1"),
+            Ok(Object::Function {
+                argument_count: Arity::Exact(1),
+                ..
+            })
+        ));
+
+        assert_eq!(bloodbath.eval_str("inc inc 1"), Ok(Object::Integer(3)));
+        assert_eq!(
+            bloodbath.eval_str("x"),
+            Err(ParserError::EvaluationFailed(
+                RuntimeError::UndefinedVariable("x".into())
+            ))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("def fac [n] if < n 2 then 1 else * n fac - n 1"),
+            bloodbath.eval_str("identity fac")
+        );
+
+        assert_eq!(bloodbath.eval_str("fac 10"), Ok(Object::Integer(3628800)));
+
+        assert_eq!(
+            bloodbath.eval_str("{def sub3 [a b c] - a - b c sub3 5 2 1}"),
+            Ok(Object::Integer(4))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("def zero [] 0 [zero zero]"),
+            Ok(Object::List(
+                vec![Object::Integer(0), Object::Integer(0)].into()
+            ))
+        );
+
+        assert!(bloodbath.eval_str("def broken [1] 0").is_err());
+        assert!(bloodbath.eval_str("def broken [a]").is_err());
+        assert!(!bloodbath.is_defined("broken"));
+    }
+
+    #[test]
+    fn test_def_is_bound_when_evaluated() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(
+            bloodbath.eval_str("if false then def f [x] + x 1 else 0; defined? f"),
+            Ok(Object::Boolean(false))
+        );
+        assert!(!bloodbath.is_defined("f"));
+
+        assert_eq!(
+            bloodbath.eval_str("def outer [] { def inner [] 1; 0 }; inner"),
+            Err(ParserError::EvaluationFailed(
+                RuntimeError::UndefinedVariable("inner".into())
+            ))
+        );
+        assert!(!bloodbath.is_defined("inner"));
+
+        // Calling the outer function evaluates the inner `def`.
+        assert_eq!(bloodbath.eval_str("outer; inner"), Ok(Object::Integer(1)));
+
+        // The rest of the group still sees the function's arity.
+        assert_eq!(
+            bloodbath.eval_str("{ def twice [x] * x 2; twice twice 3 }"),
+            Ok(Object::Integer(12))
+        );
+
+        bloodbath.eval_str("set g 5").unwrap();
+        assert_eq!(
+            bloodbath.eval_str("if false then { def g [] 1 } else 0; g"),
+            Ok(Object::Integer(5))
+        );
+
+        let expressions = bloodbath.parse("{ def h [x] x; h 1 } h").unwrap();
+        assert_eq!(expressions.len(), 2);
+        assert!(!bloodbath.is_defined("h"));
+    }
+
+    #[test]
+    fn test_recursion_limit() {
+        // Debug builds use more stack per call than the default test thread
+        // has to reach the default limit.
+        let handle = std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(|| {
+                let mut bloodbath = Bloodbath::new();
+
+                bloodbath.eval_str("def forever [n] forever + n 1").unwrap();
+
+                assert_eq!(
+                    bloodbath.eval_str("forever 0"),
+                    Err(ParserError::EvaluationFailed(
+                        RuntimeError::RecursionLimitExceeded
+                    ))
+                );
+
+                assert_eq!(
+                    bloodbath.eval_str("n"),
+                    Err(ParserError::EvaluationFailed(
+                        RuntimeError::UndefinedVariable("n".into())
+                    ))
+                );
+
+                bloodbath
+                    .eval_str("def count [n] if < n 1 then 0 else + 1 count - n 1")
+                    .unwrap();
+
+                assert_eq!(bloodbath.eval_str("count 990"), Ok(Object::Integer(990)));
+
+                bloodbath
+                    .eval_str("def through [n] if < n 1 then 0 else + 1 call quote through [- n 1]")
+                    .unwrap();
+
+                assert_eq!(bloodbath.eval_str("through 490"), Ok(Object::Integer(490)));
+
+                assert_eq!(
+                    bloodbath.eval_str("count 1000"),
+                    Err(ParserError::EvaluationFailed(
+                        RuntimeError::RecursionLimitExceeded
+                    ))
+                );
+            });
+
+        handle.unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn test_recursive_functions_are_freed() {
+        let mut bloodbath = Bloodbath::new();
+
+        let define = |bloodbath: &mut Bloodbath| {
+            bloodbath
+                .eval_str("def count [n] if < n 1 then 0 else + 1 count - n 1")
+                .unwrap();
+
+            match bloodbath.variable_get("count").unwrap().as_ref() {
+                Object::Function {
+                    implementation: FunctionImplementation::User(function),
+                    ..
+                } => Rc::downgrade(function),
+                _ => unreachable!(),
+            }
+        };
+
+        let function = define(&mut bloodbath);
+        assert_eq!(bloodbath.eval_str("count 3"), Ok(Object::Integer(3)));
+
+        bloodbath.eval_str("def count [n] n").unwrap();
+        assert!(function.upgrade().is_none());
+
+        let function = define(&mut bloodbath);
+        bloodbath.reset();
+        assert!(function.upgrade().is_none());
+
+        let function = define(&mut bloodbath);
+        bloodbath.eval_str("set alias quote count").unwrap();
+        bloodbath.eval_str("set count 1").unwrap();
+        assert_eq!(
+            bloodbath.eval_str("call quote alias [2]"),
+            Ok(Object::Integer(2))
+        );
+
+        bloodbath.eval_str("set alias 1").unwrap();
+        assert!(function.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_cond() {
+        let mut bloodbath = Bloodbath::new();
+
+        bloodbath.eval_str("set x 5").unwrap();
+
+        assert_eq!(
+            bloodbath.eval_str(r#"cond < x 0 "negative" < x 10 "small" true "large" end"#),
+            Ok(Object::String("small".into()))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str(r#"cond < x 0 "negative" > x 10 "large" end"#),
+            Ok(Object::Noop)
+        );
+
+        assert_eq!(
+            bloodbath.eval_str(r#"cond < x 0 "negative" else "other" end"#),
+            Ok(Object::String("other".into()))
+        );
+
+        assert_eq!(bloodbath.eval_str("cond end"), Ok(Object::Noop));
+        assert_eq!(
+            bloodbath.eval_str("cond true + 1 2 end"),
+            Ok(Object::Integer(3))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str(r#"cond error "bad" 1 end"#),
+            Ok(Object::Error("bad".into()))
+        );
+
+        assert!(bloodbath.eval_str("cond true 1").is_err());
+        assert!(bloodbath.eval_str("cond true end").is_err());
+        assert!(bloodbath.eval_str("cond else 1 2 end").is_err());
+    }
+
+    #[test]
+    fn test_str() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(
+            bloodbath.eval_str(r#"concat "n = " str 42"#),
+            Ok(Object::String("n = 42".into()))
+        );
+        assert_eq!(
+            bloodbath.eval_str("str noop"),
+            Ok(Object::String("".into()))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str(r#"parse-number "42""#),
+            Ok(Object::Integer(42))
+        );
+        assert_eq!(
+            bloodbath.eval_str(r#"parse-number "0.25""#),
+            Ok(Object::Float(0.25))
+        );
+        assert_eq!(
+            bloodbath.eval_str("parse-number str 17"),
+            Ok(Object::Integer(17))
+        );
+        assert!(bloodbath.eval_str(r#"parse-number "garbage""#).is_err());
+    }
+
+    #[test]
+    fn test_type_of() {
+        let mut bloodbath = Bloodbath::new();
+        let type_of = |bloodbath: &mut Bloodbath, input: &str| {
+            bloodbath
+                .eval_str(&format!("type-of {}", input))
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(type_of(&mut bloodbath, "noop"), "noop");
+        assert_eq!(type_of(&mut bloodbath, "true"), "boolean");
+        assert_eq!(type_of(&mut bloodbath, "1"), "integer");
+        assert_eq!(type_of(&mut bloodbath, "1.5"), "float");
+        assert_eq!(type_of(&mut bloodbath, r#""a""#), "string");
+        assert_eq!(type_of(&mut bloodbath, "'a'"), "char");
+        assert_eq!(type_of(&mut bloodbath, "[1]"), "list");
+        assert_eq!(type_of(&mut bloodbath, "identity +"), "function");
+    }
+
+    #[test]
+    fn test_assert() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(bloodbath.eval_str("assert true"), Ok(Object::Noop));
+        assert_eq!(bloodbath.eval_str("assert-eq - 3 1 2"), Ok(Object::Noop));
+
+        assert_eq!(
+            bloodbath.eval_str("assert false"),
+            Err(ParserError::EvaluationFailed(
+                RuntimeError::AssertionFailed("assertion failed".into())
+            ))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("assert-eq - 3 1 3"),
+            Err(ParserError::EvaluationFailed(
+                RuntimeError::AssertionFailed("assertion failed: 2 != 3".into())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_and_or() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(bloodbath.eval_str("and true 2"), Ok(Object::Integer(2)));
+        assert_eq!(bloodbath.eval_str("and noop 2"), Ok(Object::Noop));
+        assert_eq!(bloodbath.eval_str("or false 2"), Ok(Object::Integer(2)));
+        assert_eq!(bloodbath.eval_str("or 1 2"), Ok(Object::Integer(1)));
+        assert_eq!(bloodbath.eval_str("or false noop"), Ok(Object::Noop));
+
+        // The right-hand side would fail if it were evaluated.
+        assert_eq!(
+            bloodbath.eval_str("and false assert false"),
+            Ok(Object::Boolean(false))
+        );
+        assert_eq!(
+            bloodbath.eval_str("or true assert false"),
+            Ok(Object::Boolean(true))
+        );
+        assert!(bloodbath.eval_str("and true assert false").is_err());
+        assert!(bloodbath.eval_str("or false assert false").is_err());
+
+        assert_eq!(
+            bloodbath.eval_str(r#"or error "left" 1"#),
+            Ok(Object::Error("left".into()))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("if and < 1 2 < 2 3 then 1 else 0"),
+            Ok(Object::Integer(1))
+        );
+
+        assert!(bloodbath.eval_str("and true").is_err());
+        assert!(bloodbath.eval_str("{or}").is_err());
+    }
+
+    #[test]
+    fn test_when_unless() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(bloodbath.eval_str("when true 1"), Ok(Object::Integer(1)));
+        assert_eq!(bloodbath.eval_str("when false 1"), Ok(Object::Noop));
+        assert_eq!(bloodbath.eval_str("unless false 1"), Ok(Object::Integer(1)));
+        assert_eq!(bloodbath.eval_str("unless true 1"), Ok(Object::Noop));
+
+        assert_eq!(
+            bloodbath.eval_str("when false assert false"),
+            Ok(Object::Noop)
+        );
+        assert_eq!(
+            bloodbath.eval_str("unless true assert false"),
+            Ok(Object::Noop)
+        );
+
+        bloodbath.eval_str("set x 0").unwrap();
+        bloodbath.eval_str("when < x 1 set! x 5").unwrap();
+        assert_eq!(bloodbath.eval_str("x"), Ok(Object::Integer(5)));
+
+        assert!(bloodbath.eval_str("when true").is_err());
+        assert!(bloodbath.eval_str("{unless}").is_err());
+    }
+
+    #[test]
+    fn test_repeat() {
+        let mut bloodbath = Bloodbath::new();
+
+        bloodbath.eval_str("set c 0").unwrap();
+        assert_eq!(
+            bloodbath.eval_str("repeat 3 {set c + c 1}"),
+            Ok(Object::Integer(3))
+        );
+        assert_eq!(bloodbath.eval_str("c"), Ok(Object::Integer(3)));
+
+        assert_eq!(bloodbath.eval_str("repeat 0 {set c 100}"), Ok(Object::Noop));
+        assert_eq!(bloodbath.eval_str("c"), Ok(Object::Integer(3)));
+
+        assert!(bloodbath.eval_str("repeat -1 1").is_err());
+        assert!(bloodbath.eval_str("repeat 1.5 1").is_err());
+        assert!(bloodbath.eval_str("repeat 3").is_err());
+    }
+
+    #[test]
+    fn test_division() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(bloodbath.eval_str("/ 4 2"), Ok(Object::Float(2.0)));
+        assert_eq!(bloodbath.eval_str("/ 5 2"), Ok(Object::Float(2.5)));
+        assert_eq!(bloodbath.eval_str("/ 7 2"), Ok(Object::Float(3.5)));
+        assert_eq!(bloodbath.eval_str("/ 4.0 2.0"), Ok(Object::Float(2.0)));
+        assert!(bloodbath.eval_str("/ 4 0").is_err());
+        assert!(bloodbath.eval_str("/ 0 0").is_err());
+
+        assert_eq!(bloodbath.eval_str("// 4 2"), Ok(Object::Integer(2)));
+        assert_eq!(bloodbath.eval_str("// 7 2"), Ok(Object::Integer(3)));
+        assert_eq!(bloodbath.eval_str("// -7 2"), Ok(Object::Integer(-4)));
+        assert_eq!(bloodbath.eval_str("// 7 -2"), Ok(Object::Integer(-4)));
+        assert_eq!(bloodbath.eval_str("// -6 2"), Ok(Object::Integer(-3)));
+        assert!(bloodbath.eval_str("// 7.5 2").is_err());
+        assert!(bloodbath.eval_str("// 4 0").is_err());
+        assert!(bloodbath.eval_str("// 4 [2]").is_err());
+
+        assert_eq!(
+            bloodbath.eval_str("// -9223372036854775808 -1"),
+            Err(ParserError::EvaluationFailed(
+                RuntimeError::InvalidArgument("`//`: integer overflow".into())
+            ))
+        );
+        assert_eq!(
+            bloodbath.eval_str("// -9223372036854775808 1"),
+            Ok(Object::Integer(i64::MIN))
+        );
+    }
+
+    #[test]
+    fn test_round() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(bloodbath.eval_str("round 2.5"), Ok(Object::Float(2.0)));
+        assert_eq!(bloodbath.eval_str("round 2.4"), Ok(Object::Float(2.0)));
+        assert_eq!(
+            bloodbath.eval_str("round 3.14159 2"),
+            Ok(Object::Float(314.0 / 100.0))
+        );
+        assert_eq!(
+            bloodbath.eval_str("{round 3.14159} 2"),
+            Ok(Object::Integer(2))
+        );
+    }
+
+    #[test]
+    fn test_trigonometry() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(bloodbath.eval_str("sin 0"), Ok(Object::Float(0.0)));
+        assert_eq!(bloodbath.eval_str("cos 0"), Ok(Object::Float(1.0)));
+
+        let pi = bloodbath.eval_str("pi").unwrap().get_float().unwrap();
+        assert!((pi - std::f64::consts::PI).abs() < f64::EPSILON);
+
+        let value = bloodbath.eval_str("cos pi").unwrap().get_float().unwrap();
+        assert!((value + 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_log_exp() {
+        let mut bloodbath = Bloodbath::new();
+        let mut float = |input| bloodbath.eval_str(input).unwrap().get_float().unwrap();
+
+        assert!((float("log exp 1") - 1.0).abs() < 1e-12);
+        assert!((float("log 8 2") - 3.0).abs() < 1e-12);
+
+        assert!(bloodbath.eval_str("log 0").is_err());
+        assert!(bloodbath.eval_str("log -1").is_err());
+    }
+
+    #[test]
+    fn test_list_primitives() {
+        let mut bloodbath = Bloodbath::new();
+
+        bloodbath.eval_str("set xs cons 0 [1 2]").unwrap();
+        assert_eq!(bloodbath.eval_str("xs"), bloodbath.eval_str("[0 1 2]"));
+
+        assert_eq!(bloodbath.eval_str("first xs"), Ok(Object::Integer(0)));
+        assert_eq!(bloodbath.eval_str("first rest xs"), Ok(Object::Integer(1)));
+        assert_eq!(
+            bloodbath.eval_str("rest rest xs"),
+            bloodbath.eval_str("[2]")
+        );
+        assert_eq!(
+            bloodbath.eval_str("rest rest rest xs"),
+            bloodbath.eval_str("[]")
+        );
+
+        assert!(bloodbath.eval_str("first []").is_err());
+        assert!(bloodbath.eval_str("rest []").is_err());
+
+        assert_eq!(
+            bloodbath.eval_str("append [1 2] [3 4]"),
+            bloodbath.eval_str("[1 2 3 4]")
+        );
+        assert_eq!(bloodbath.eval_str("append xs []"), bloodbath.eval_str("xs"));
+        assert_eq!(bloodbath.eval_str("xs"), bloodbath.eval_str("[0 1 2]"));
+    }
+
+    #[test]
+    fn test_maps() {
+        let mut bloodbath = Bloodbath::new();
+
+        bloodbath.eval_str("set m map-new").unwrap();
+        bloodbath.eval_str("set m map-set m 1 \"one\"").unwrap();
+        bloodbath.eval_str("set m map-set m \"two\" 2").unwrap();
+
+        assert_eq!(
+            bloodbath.eval_str("map-get m 1"),
+            Ok(Object::String("one".into()))
+        );
+        assert_eq!(
+            bloodbath.eval_str("map-get m \"two\""),
+            Ok(Object::Integer(2))
+        );
+        assert_eq!(bloodbath.eval_str("map-get m 3"), Ok(Object::Noop));
+        assert_eq!(bloodbath.eval_str("map-has m 1"), Ok(Object::Boolean(true)));
+        assert_eq!(
+            bloodbath.eval_str("map-has m 2"),
+            Ok(Object::Boolean(false))
+        );
+        assert_eq!(
+            bloodbath.eval_str("type-of m"),
+            Ok(Object::String("map".into()))
+        );
+
+        assert!(bloodbath.eval_str("map-get m 1.5").is_err());
+        assert!(bloodbath.eval_str("map-set m [1] 2").is_err());
+
+        assert_eq!(bloodbath.eval_str("length keys m"), Ok(Object::Integer(2)));
+        assert_eq!(
+            bloodbath.eval_str("length values m"),
+            Ok(Object::Integer(2))
+        );
+        assert_eq!(
+            bloodbath.eval_str("map-get m first keys m"),
+            bloodbath.eval_str("first values m")
+        );
+        assert!(bloodbath.eval_str("keys [1 2]").is_err());
+    }
+
+    #[test]
+    fn test_terminators() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(
+            bloodbath.eval_str("- 1;"),
+            Err(ParserError::NotEnoughArguments(
+                "-".into(),
+                Arity::Exact(2),
+                1
+            ))
+        );
+        assert_eq!(
+            bloodbath.eval_str("- 1; 2").unwrap_err().to_string(),
+            "`-` expects 2 arguments, got 1"
+        );
+        assert_eq!(
+            bloodbath.eval_str("neg;").unwrap_err().to_string(),
+            "`neg` expects 1 argument, got 0"
+        );
+
+        assert_eq!(
+            bloodbath.eval_all("+ 1 2; 3"),
+            Ok(vec![Object::Integer(3), Object::Integer(3)])
+        );
+        assert_eq!(bloodbath.eval_all("+ 1 2 3"), Ok(vec![Object::Integer(6)]));
+
+        assert_eq!(bloodbath.eval_str("set a 1;a;;"), Ok(Object::Integer(1)));
+        assert_eq!(bloodbath.eval_str(";;"), Ok(Object::Noop));
+        assert_eq!(bloodbath.eval_str("{+ 1 2; 4;}"), Ok(Object::Integer(4)));
+        assert_eq!(bloodbath.parse("1; 2").map(|x| x.len()), Ok(2));
+
+        assert_eq!(
+            bloodbath.eval_str("[1; 2]"),
+            Err(ParserError::UnexpectedTerminator)
+        );
+        assert_eq!(
+            bloodbath.eval_str("{1;"),
+            Err(ParserError::UnterminatedCompoundExpression)
+        );
+    }
+
+    #[test]
+    fn test_try() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(
+            bloodbath.eval_str("try / 1 0 catch 42"),
+            Ok(Object::Integer(42))
+        );
+        assert_eq!(
+            bloodbath.eval_str("try // 4 2 catch 42"),
+            Ok(Object::Integer(2))
+        );
+
+        bloodbath.eval_str("set ran false").unwrap();
+        assert_eq!(
+            bloodbath.eval_str("try + 1 2 catch set! ran true"),
+            Ok(Object::Integer(3))
+        );
+        assert_eq!(bloodbath.eval_str("ran"), Ok(Object::Boolean(false)));
+
+        assert_eq!(
+            bloodbath.eval_str("try assert false catch error-message"),
+            Ok(Object::String("assertion failed".into()))
+        );
+        assert_eq!(
+            bloodbath.eval_str(r#"try error "oops" catch error-message"#),
+            Ok(Object::String("oops".into()))
+        );
+        assert_eq!(
+            bloodbath.eval_str("error-message"),
+            Err(ParserError::EvaluationFailed(
+                RuntimeError::UndefinedVariable("error-message".into())
+            ))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("try {set! missing 1} catch try / 1 0 catch 7"),
+            Ok(Object::Integer(7))
+        );
+
+        assert_eq!(
+            bloodbath.eval_str("try / 1 0 catch throw error-message"),
+            Err(ParserError::EvaluationFailed(RuntimeError::Thrown(
+                "`/` expects a non-zero divisor".into()
+            )))
+        );
+        assert_eq!(
+            bloodbath.eval_str(r#"try / 1 0 catch throw "something else""#),
+            Err(ParserError::EvaluationFailed(RuntimeError::Thrown(
+                "something else".into()
+            )))
+        );
+        assert_eq!(
+            bloodbath.eval_str(
+                r#"try {try throw "inner" catch throw error-message} catch error-message"#
+            ),
+            Ok(Object::String("inner".into()))
+        );
+        assert_eq!(
+            bloodbath.eval_str(r#"try throw "inner" catch "handled""#),
+            Ok(Object::String("handled".into()))
+        );
+        assert!(bloodbath.eval_str("throw 1").is_err());
+
+        bloodbath.set_step_limit(100);
+        assert_eq!(
+            bloodbath.eval_str("try while true do 1 catch 0"),
+            Err(ParserError::EvaluationFailed(
+                RuntimeError::StepLimitExceeded
+            ))
+        );
+
+        assert!(bloodbath.eval_str("try 1").is_err());
+        assert!(bloodbath.eval_str("try 1 catch").is_err());
+    }
+
+    #[test]
+    fn test_for() {
+        let output = SharedOutput::default();
+        let mut bloodbath = Bloodbath::new().with_output(output.clone());
+
+        bloodbath.eval_str("set x 10 set sum 0").unwrap();
+        assert_eq!(
+            bloodbath.eval_str("for x [1 2 3] set! sum + sum x"),
+            Ok(Object::Noop)
+        );
+        assert_eq!(bloodbath.eval_str("sum"), Ok(Object::Integer(6)));
+        assert_eq!(bloodbath.eval_str("x"), Ok(Object::Integer(10)));
+
+        bloodbath.eval_str("for y [1 2] {print y}").unwrap();
+        assert_eq!(
+            String::from_utf8(output.0.borrow().clone()).unwrap(),
+            "1\n2\n"
+        );
+        assert_eq!(
+            bloodbath.eval_str("y"),
+            Err(ParserError::EvaluationFailed(
+                RuntimeError::UndefinedVariable("y".into())
+            ))
+        );
+
+        assert_eq!(bloodbath.eval_str("for z [] 1"), Ok(Object::Noop));
+        assert_eq!(
+            bloodbath.eval_str("for z 5 1").unwrap_err().to_string(),
+            "`for` expects a list, got an integer"
+        );
+        assert!(bloodbath.eval_str("for 1 [1] 1").is_err());
+    }
+
+    #[test]
+    fn test_while() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(
+            bloodbath.eval_str("set i 0 while < i 10 do set i + i 1"),
+            Ok(Object::Integer(10))
+        );
+
+        assert_eq!(bloodbath.eval_str("i"), Ok(Object::Integer(10)));
+        assert_eq!(bloodbath.eval_str("while false do 1"), Ok(Object::Noop));
+
+        assert_eq!(
+            bloodbath.eval_str(r#"while true do error "stop""#),
+            Ok(Object::Error("stop".into()))
+        );
+
+        assert!(bloodbath.eval_str("while true").is_err());
+        assert!(bloodbath.eval_str("while true do").is_err());
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut bloodbath = Bloodbath::new();
+        bloodbath.set_step_limit(1000);
+
+        assert_eq!(
+            bloodbath.eval_str("set i 0 while < i 1000 do set i + i 1"),
+            Err(ParserError::EvaluationFailed(
+                RuntimeError::StepLimitExceeded
+            ))
+        );
+
+        // The step count starts over with every evaluation.
+        assert_eq!(
+            bloodbath.eval_str("set i 0 while < i 10 do set i + i 1"),
+            Ok(Object::Integer(10))
+        );
+
+        bloodbath.set_step_limit(100_000);
+
+        assert_eq!(
+            bloodbath.eval_str("set i 0 while < i 1000 do set i + i 1"),
+            Ok(Object::Integer(1000))
+        );
+
+        bloodbath.set_recursion_limit(10);
+        bloodbath
+            .eval_str("def count [n] if < n 1 then 0 else + 1 count - n 1")
+            .unwrap();
+
+        assert_eq!(bloodbath.eval_str("count 5"), Ok(Object::Integer(5)));
+
+        assert_eq!(
+            bloodbath.eval_str("count 20"),
+            Err(ParserError::EvaluationFailed(
+                RuntimeError::RecursionLimitExceeded
+            ))
+        );
+    }
+
+    #[test]
+    fn test_eval_with_timeout() {
+        let mut bloodbath = Bloodbath::new();
+        let timeout = Duration::from_millis(50);
+
+        assert_eq!(
+            bloodbath.eval_with_timeout("while true do 1", timeout),
+            Err(ParserError::EvaluationFailed(RuntimeError::Timeout))
+        );
+
+        assert_eq!(
+            bloodbath.eval_with_timeout("try while true do 1 catch 0", timeout),
+            Err(ParserError::EvaluationFailed(RuntimeError::Timeout))
+        );
+
+        assert_eq!(
+            bloodbath.eval_with_timeout("+ 1 2", timeout),
+            Ok(Object::Integer(3))
+        );
+
+        // The deadline doesn't outlive the call.
+        std::thread::sleep(timeout);
+        assert_eq!(bloodbath.eval_str("repeat 5000 1"), Ok(Object::Integer(1)));
+    }
+
+    #[test]
+    fn test_eval_all() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(
+            bloodbath.eval_all("1 2 3"),
+            Ok(vec![
+                Object::Integer(1),
+                Object::Integer(2),
+                Object::Integer(3)
+            ])
+        );
+
+        assert_eq!(bloodbath.eval_all(""), Ok(vec![]));
+
+        assert_eq!(
+            bloodbath.eval_all("set x 1 - x 3"),
+            Ok(vec![Object::Integer(1), Object::Integer(-2)])
+        );
+
+        assert!(bloodbath.eval_all("1 }").is_err());
+    }
+
+    #[test]
+    fn test_eval_borrowed() {
+        let mut bloodbath = Bloodbath::new();
+
+        assert_eq!(bloodbath.eval("+ 1 2"), Ok(Object::Integer(3)));
+        assert_eq!(
+            bloodbath.eval(String::from("+ 1 2")),
+            Ok(Object::Integer(3))
+        );
+
+        let source = String::from("set x 4");
+        assert_eq!(bloodbath.eval(&source), Ok(Object::Integer(4)));
+        assert_eq!(bloodbath.eval_str("x"), Ok(Object::Integer(4)));
+    }
+
+    #[test]
+    fn test_shared_values() {
+        let mut bloodbath = Bloodbath::new();
+
+        bloodbath.variable_set(
+            "big",
+            Object::List(vec![Object::Integer(0); 100_000].into()),
+        );
+
+        let first = bloodbath.variable_get("big").unwrap();
+        let second = bloodbath.variable_get("big").unwrap();
+
+        assert!(Rc::ptr_eq(&first, &second));
+
+        bloodbath.eval_str("set big 1").unwrap();
+
+        assert_eq!(
+            bloodbath.variable_get("big").as_deref(),
+            Some(&Object::Integer(1))
+        );
+        assert_eq!(first.get_list().map(Vec::len), Some(100_000));
+
+        assert_eq!(bloodbath.variable_get("fresh"), None);
+        assert!(!bloodbath.is_defined("fresh"));
+    }
+
+    #[test]
+    fn test_shared_values_in_scripts() {
+        let mut bloodbath = Bloodbath::new();
+
+        let big = Rc::new(vec![Object::Integer(0); 100_000]);
+        bloodbath.variable_set("big", Object::List(big.clone()));
+
+        // Every copy of the list made by the script is another reference to
+        // the same items, counted by the `Rc`.
+        let result = bloodbath.eval_str("def same [x] x [big same big]").unwrap();
+        assert_eq!(Rc::strong_count(&big), 4);
+
+        match result.get_list().map(Vec::as_slice) {
+            Some([Object::List(first), Object::List(second)]) => {
+                assert!(Rc::ptr_eq(first, &big));
+                assert!(Rc::ptr_eq(second, &big));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        drop(result);
+        assert_eq!(Rc::strong_count(&big), 2);
+
+        assert_eq!(
+            bloodbath.eval_str("repeat 100 length big; length big"),
+            Ok(Object::Integer(100_000))
+        );
+        assert_eq!(Rc::strong_count(&big), 2);
+    }
+
+    #[test]
+    fn test_register_constant() {
+        let mut bloodbath = Bloodbath::new();
+
+        bloodbath.register_constant("flags", Object::List(vec![Object::Boolean(true)].into()));
+        bloodbath.register_int("answer", 42);
+        bloodbath.register_float("half", 0.5);
+        bloodbath.register_str("version", "1.2.3");
+
+        assert_eq!(bloodbath.eval_str("- answer 2"), Ok(Object::Integer(40)));
+        assert_eq!(bloodbath.eval_str("* half 4"), Ok(Object::Float(2.0)));
+        assert_eq!(bloodbath.eval_str("first flags"), Ok(Object::Boolean(true)));
+        assert_eq!(
+            bloodbath.eval_str(r#"concat "v" version"#),
+            Ok(Object::String("v1.2.3".into()))
+        );
+    }
+
+    #[test]
+    fn test_describe() {
+        let mut bloodbath = Bloodbath::new();
+
+        bloodbath.register_doc("twice", Arity::Exact(1), "Doubles a number.", |args| {
+            crate::builtins::add(vec![args[0].clone(), args[0].clone()])
+        });
+
+        assert_eq!(
+            bloodbath.describe("twice"),
+            Some("Doubles a number.".into())
+        );
+        assert_eq!(bloodbath.eval_str("twice 4"), Ok(Object::Integer(8)));
+
+        assert!(bloodbath.describe("+").is_some());
+        assert_eq!(bloodbath.describe("nothing"), None);
+
+        bloodbath.register("undocumented", Arity::Exact(0), |_| Ok(Object::Noop));
+        assert_eq!(bloodbath.describe("undocumented"), None);
+    }
+
+    #[test]
+    fn test_eval_reader() {
+        let mut bloodbath = Bloodbath::new();
+        let script = std::io::Cursor::new("def sq [x] {* x x}\nset y sq 4\n+ y 1");
+
+        assert_eq!(bloodbath.eval_reader(script), Ok(Object::Integer(17)));
+        assert_eq!(bloodbath.eval_str("y"), Ok(Object::Integer(16)));
+
+        assert_eq!(
+            bloodbath.eval_reader(std::io::Cursor::new(b"\xFF".to_vec())),
+            Err(ParserError::ReadingFailed(ReaderError::IoFailed(
+                "input is not valid UTF-8".into()
+            )))
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let mut bloodbath = Bloodbath::new();
+        let expressions = bloodbath.parse("+ 1 2 x").unwrap();
+
+        assert_eq!(expressions.len(), 1);
+
+        match &expressions[0] {
+            Expression::FunctionCall(_, arguments) => {
+                assert_eq!(arguments.len(), 3);
+                assert!(matches!(
+                    arguments[0],
+                    Expression::Constant(Object::Integer(1))
+                ));
+                assert!(matches!(arguments[2], Expression::Variable(ref name) if name == "x"));
+            }
+            _ => unreachable!(),
+        }
+
+        let expressions = bloodbath.parse("def f [a] a f 1 set y 2").unwrap();
+
+        assert_eq!(expressions.len(), 3);
+        assert!(matches!(expressions[1], Expression::FunctionCall(_, _)));
+
+        // Parsing must not define anything.
+        assert!(!bloodbath.is_defined("f"));
+        assert!(!bloodbath.is_defined("x"));
+        assert!(!bloodbath.is_defined("y"));
+
+        assert_eq!(
+            bloodbath.parse("- 1").err(),
+            Some(ParserError::NotEnoughArguments(
+                "-".into(),
+                Arity::Exact(2),
+                1
+            ))
+        );
+
+        assert!(bloodbath.parse("\"oops").is_err());
+    }
+
+    #[test]
+    fn test_expression_debug() {
+        let mut bloodbath = Bloodbath::new();
+        let expressions = bloodbath.parse("if < x 1 then 1 else [2]").unwrap();
+
+        assert_eq!(
+            format!("{:?}", expressions[0]),
+            "If(FunctionCall(<builtin>, [Variable(\"x\"), Constant(Integer(1))]), \
+             Constant(Integer(1)), Some(List([Constant(Integer(2))])))"
+        );
+    }
+
+    #[test]
+    fn test_error_display() {
+        let mut bloodbath = Bloodbath::new();
+        let message = |bloodbath: &mut Bloodbath, input: &str| {
+            bloodbath.eval_str(input).unwrap_err().to_string()
+        };
+
+        assert_eq!(
+            message(&mut bloodbath, "- 1"),
+            "`-` expects 2 arguments, got 1"
+        );
+        assert_eq!(message(&mut bloodbath, "{1"), "Missing a closing `}`");
+        assert_eq!(message(&mut bloodbath, "[1"), "Missing a closing `]`");
+        assert_eq!(message(&mut bloodbath, "]"), "Unexpected bracket");
+        assert_eq!(
+            message(&mut bloodbath, "if 1 2"),
+            "Expected `then` after the `if` condition"
+        );
+        assert_eq!(
+            message(&mut bloodbath, "\"a"),
+            "Unterminated string literal"
+        );
+        assert_eq!(
+            message(&mut bloodbath, "set! nope 1"),
+            "`nope` is not defined"
+        );
+        assert_eq!(
+            message(&mut bloodbath, "- 1 \"a\""),
+            "`-` expects two numbers"
+        );
+
+        assert_eq!(
+            RuntimeError::RecursionLimitExceeded.to_string(),
+            "Maximum recursion depth exceeded"
+        );
+    }
+
+    #[test]
+    fn test_error_source() {
+        fn run(input: &str) -> Result<Object, Box<dyn std::error::Error>> {
+            let mut bloodbath = Bloodbath::new();
+            Ok(bloodbath.eval(input)?)
+        }
+
+        assert_eq!(run("+ 1 2").unwrap(), Object::Integer(3));
+
+        let err = run("\"a").unwrap_err();
+        let source = err.source().unwrap();
+
+        assert_eq!(
+            source.downcast_ref::<ReaderError>(),
+            Some(&ReaderError::UnterminatedString)
+        );
+
+        let err = run("assert false").unwrap_err();
+
+        assert_eq!(
+            err.source().unwrap().downcast_ref::<RuntimeError>(),
+            Some(&RuntimeError::AssertionFailed("assertion failed".into()))
+        );
+
+        assert!(run("- 1").unwrap_err().source().is_none());
+    }
 }
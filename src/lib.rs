@@ -0,0 +1,27 @@
+pub mod builtins;
+pub mod clock;
+pub mod interpreter;
+pub mod object;
+pub mod random;
+pub mod reader;
+
+pub fn version() -> &'static str {
+    env!("CARGO_PKG_VERSION")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version() {
+        assert!(!version().is_empty());
+
+        // Ignore any pre-release or build metadata suffix.
+        let core = version().split(['-', '+']).next().unwrap();
+        let parts: Vec<&str> = core.split('.').collect();
+
+        assert_eq!(parts.len(), 3);
+        assert!(parts.iter().all(|part| part.parse::<u64>().is_ok()));
+    }
+}
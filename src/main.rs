@@ -1,21 +1,144 @@
-use crate::interpreter::Bloodbath;
-use crate::interpreter::ParserError;
-use crate::reader::ReaderError;
+use bloodbath::interpreter::Bloodbath;
+use bloodbath::interpreter::STACK_SIZE;
+use bloodbath::object::Object;
+use bloodbath::reader::is_complete;
 use std::io::Write;
 
-mod builtins;
-mod interpreter;
-mod object;
-mod reader;
+// Parses a `:!N` history recall command into the index `N`.
+fn parse_recall(line: &str) -> Option<usize> {
+    line.strip_prefix(":!")?.trim().parse().ok()
+}
+
+// Parses a command like `:help NAME` into its argument.
+fn parse_command<'a>(line: &'a str, command: &str) -> Option<&'a str> {
+    let name = line.strip_prefix(command)?.strip_prefix(' ')?.trim();
+
+    if name.is_empty() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+// Capitalises the object's type name, e.g. `Integer` for `integer`.
+fn type_label(object: &Object) -> String {
+    let name = object.type_name();
+    let mut chars = name.chars();
+
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+// Formats a result like `3 : Integer`. Returns `None` for `Noop`, which isn't
+// worth echoing.
+fn format_result(object: &Object) -> Option<String> {
+    match object {
+        Object::Noop => None,
+        _ => Some(format!("{} : {}", object, type_label(object))),
+    }
+}
+
+enum Mode {
+    Repl,
+    Eval(String),
+    Version,
+}
+
+fn parse_args(args: &[String]) -> Result<Mode, String> {
+    match args {
+        [] => Ok(Mode::Repl),
+        [flag] if flag == "--version" => Ok(Mode::Version),
+        [flag, expression] if flag == "--eval" => Ok(Mode::Eval(expression.clone())),
+        [flag] if flag == "--eval" => Err("`--eval` expects an expression".into()),
+        [arg, ..] => Err(format!("Unexpected argument `{}`", arg)),
+    }
+}
+
+// Evaluates a single expression and prints its result. Returns the exit code.
+fn run_eval(expression: &str, output: &mut impl Write, errors: &mut impl Write) -> i32 {
+    let mut bloodbath = Bloodbath::new();
+
+    let written = match bloodbath.eval(expression) {
+        Ok(Object::Error(message)) => {
+            let _ = writeln!(errors, "Error: {}", message);
+            return 1;
+        }
+        Ok(Object::Noop) => Ok(()),
+        Ok(object) => writeln!(output, "{}", object),
+        Err(err) => {
+            let _ = writeln!(errors, "{}", err);
+            return 1;
+        }
+    };
+
+    match written {
+        Ok(()) => 0,
+        Err(err) => {
+            let _ = writeln!(errors, "IO error: {}", err);
+            1
+        }
+    }
+}
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let mode = match parse_args(&args) {
+        Ok(mode) => mode,
+        Err(err) => {
+            eprintln!("{}", err);
+            eprintln!("Usage: bloodbath [--eval EXPRESSION | --version]");
+            std::process::exit(2);
+        }
+    };
+
+    // Scripts recurse natively in the evaluator, so give it enough stack to
+    // reach the recursion limit even in debug builds.
+    let code = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || match mode {
+            Mode::Repl => {
+                repl();
+                0
+            }
+            Mode::Eval(expression) => {
+                run_eval(&expression, &mut std::io::stdout(), &mut std::io::stderr())
+            }
+            Mode::Version => {
+                println!("bloodbath {}", bloodbath::version());
+                0
+            }
+        })
+        .expect("failed to spawn the interpreter thread")
+        .join()
+        .expect("the interpreter thread panicked");
+
+    std::process::exit(code);
+}
+
+fn repl() {
     let mut bloodbath = Bloodbath::new();
 
-    println!("Welcome to the Bloodbath REPL!");
+    println!("Welcome to the Bloodbath REPL v{}!", bloodbath::version());
     println!("Enter an expression to evaluate it. Type \"quit\" to exit.");
+    println!("Type \":env\" to list every defined variable and function.");
+    println!("Type \":history\" to list past inputs and \":!N\" to re-run input N.");
+    println!("Type \":help NAME\" to describe a builtin.");
+    println!("Type \":restore NAME\" to undo overwriting a builtin.");
+    println!("Type \":trace on\" or \":trace off\" to toggle tracing evaluation.");
+    println!("Type \":reset\" to clear every variable and function you defined.");
+
+    let mut input = String::new();
+    let mut history: Vec<String> = Vec::new();
 
     loop {
-        print!("> ");
+        if input.is_empty() {
+            print!("> ");
+        } else {
+            print!("... ");
+        }
 
         if let Err(err) = std::io::stdout().flush() {
             println!("IO error: {}", err);
@@ -24,9 +147,16 @@ fn main() {
 
         let mut line = String::new();
 
-        if let Err(err) = std::io::stdin().read_line(&mut line) {
-            println!("IO error: {}", err);
-            std::process::exit(1);
+        match std::io::stdin().read_line(&mut line) {
+            Ok(0) => {
+                println!();
+                break;
+            }
+            Ok(_) => {}
+            Err(err) => {
+                println!("IO error: {}", err);
+                std::process::exit(1);
+            }
         }
 
         // Strip line ending.
@@ -38,20 +168,198 @@ fn main() {
             }
         }
 
-        if line == "quit".to_string() {
-            println!("Goodbye!");
-            break;
+        if input.is_empty() {
+            if line == "quit" {
+                println!("Goodbye!");
+                break;
+            }
+
+            if line == ":env" {
+                for (name, description) in bloodbath.list_bindings() {
+                    println!("{} : {}", name, description);
+                }
+
+                continue;
+            }
+
+            if let Some(name) = parse_command(&line, ":help") {
+                match bloodbath.describe(name) {
+                    Some(doc) => println!("{}: {}", name, doc),
+                    None => println!("No description for `{}`", name),
+                }
+
+                continue;
+            }
+
+            if let Some(name) = parse_command(&line, ":restore") {
+                if bloodbath.restore_builtin(name) {
+                    println!("Restored `{}`.", name);
+                } else {
+                    println!("No builtin named `{}`", name);
+                }
+
+                continue;
+            }
+
+            if let Some(state) = parse_command(&line, ":trace") {
+                match state {
+                    "on" => bloodbath.set_trace(true),
+                    "off" => bloodbath.set_trace(false),
+                    _ => println!("Expected `:trace on` or `:trace off`"),
+                }
+
+                continue;
+            }
+
+            if line == ":reset" {
+                bloodbath.reset();
+                println!("Cleared every binding.");
+                continue;
+            }
+
+            if line == ":history" {
+                for (index, entry) in history.iter().enumerate() {
+                    println!("{}: {}", index, entry);
+                }
+
+                continue;
+            }
+
+            if let Some(index) = parse_recall(&line) {
+                match history.get(index) {
+                    Some(entry) => {
+                        println!("{}", entry);
+                        line = entry.clone();
+                    }
+                    None => {
+                        println!("No history entry with index {}", index);
+                        continue;
+                    }
+                }
+            }
+        }
+
+        input.push_str(&line);
+        input.push('\n');
+
+        // Keep reading lines until all braces and strings are closed.
+        if !is_complete(&input) {
+            continue;
+        }
+
+        let line = std::mem::take(&mut input);
+
+        if !line.trim().is_empty() {
+            history.push(line.trim_end().to_string());
         }
 
         match bloodbath.eval(line) {
-            Ok(object) => println!("{:?}", object),
-            Err(ParserError::ReadingFailed(err)) => match err {
-                ReaderError::EoF => println!("Unexpected end of file"),
-                ReaderError::UnexpectedCharacter(bad_char) => {
-                    println!("Unexpected character: '{}'", bad_char)
+            Ok(Object::Error(message)) => println!("Error: {}", message),
+            Ok(object) => {
+                if let Some(result) = format_result(&object) {
+                    println!("{}", result);
                 }
-            },
-            Err(err) => println!("{:?}", err),
+            }
+            Err(err) => println!("{}", err),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_recall() {
+        assert_eq!(parse_recall(":!2"), Some(2));
+        assert_eq!(parse_recall(":!0"), Some(0));
+        assert_eq!(parse_recall(":! 10"), Some(10));
+
+        assert_eq!(parse_recall(":!"), None);
+        assert_eq!(parse_recall(":!-1"), None);
+        assert_eq!(parse_recall(":!x"), None);
+        assert_eq!(parse_recall("!2"), None);
+        assert_eq!(parse_recall(":history"), None);
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command(":help +", ":help"), Some("+"));
+        assert_eq!(
+            parse_command(":help  read-line ", ":help"),
+            Some("read-line")
+        );
+        assert_eq!(parse_command(":restore +", ":restore"), Some("+"));
+
+        assert_eq!(parse_command(":help", ":help"), None);
+        assert_eq!(parse_command(":help ", ":help"), None);
+        assert_eq!(parse_command(":helpx", ":help"), None);
+        assert_eq!(parse_command(":help +", ":restore"), None);
+    }
+
+    #[test]
+    fn test_format_result() {
+        assert_eq!(type_label(&Object::Integer(3)), "Integer");
+        assert_eq!(type_label(&Object::Float(2.5)), "Float");
+        assert_eq!(type_label(&Object::Boolean(true)), "Boolean");
+        assert_eq!(type_label(&Object::String("hi".into())), "String");
+        assert_eq!(type_label(&Object::List(vec![].into())), "List");
+
+        assert_eq!(
+            format_result(&Object::Integer(3)),
+            Some("3 : Integer".into())
+        );
+        assert_eq!(
+            format_result(&Object::Float(2.5)),
+            Some("2.5 : Float".into())
+        );
+        assert_eq!(format_result(&Object::Noop), None);
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = |args: &[&str]| -> Vec<String> { args.iter().map(|x| x.to_string()).collect() };
+
+        assert!(matches!(parse_args(&args(&[])), Ok(Mode::Repl)));
+        assert!(matches!(
+            parse_args(&args(&["--version"])),
+            Ok(Mode::Version)
+        ));
+
+        assert!(matches!(
+            parse_args(&args(&["--eval", "+ 1 2"])),
+            Ok(Mode::Eval(expression)) if expression == "+ 1 2"
+        ));
+
+        assert!(parse_args(&args(&["--eval"])).is_err());
+        assert!(parse_args(&args(&["--eval", "1", "2"])).is_err());
+        assert!(parse_args(&args(&["--frobnicate"])).is_err());
+    }
+
+    #[test]
+    fn test_run_eval() {
+        let run = |expression| {
+            let mut output = Vec::new();
+            let mut errors = Vec::new();
+            let code = run_eval(expression, &mut output, &mut errors);
+
+            (
+                code,
+                String::from_utf8(output).unwrap(),
+                String::from_utf8(errors).unwrap(),
+            )
+        };
+
+        assert_eq!(run("+ 1 2"), (0, "3\n".into(), "".into()));
+        assert_eq!(run("noop"), (0, "".into(), "".into()));
+        assert_eq!(
+            run(r#"error "oops""#),
+            (1, "".into(), "Error: oops\n".into())
+        );
+
+        let (code, output, errors) = run("}");
+        assert_eq!(code, 1);
+        assert_eq!(output, "");
+        assert!(!errors.is_empty());
+    }
+}
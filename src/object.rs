@@ -1,19 +1,188 @@
+use crate::interpreter::Bloodbath;
+use crate::interpreter::EvaluationResult;
+use crate::interpreter::RuntimeError;
+use crate::interpreter::UserFunction;
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::fmt::Display;
 use std::fmt::Formatter;
+use std::hash::Hash;
+use std::hash::Hasher;
 use std::rc::Rc;
+use std::rc::Weak;
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Clone)]
 pub enum Object {
     Noop,
+    Boolean(bool),
     Integer(i64),
     Float(f64),
+    // Strings, lists and maps are shared, so that copying one around, like
+    // reading it from a variable, doesn't copy its contents.
+    String(Rc<str>),
+    Char(char),
+    List(Rc<Vec<Object>>),
+    Map(Rc<HashMap<Object, Object>>),
+    Error(String),
     Function {
-        argument_count: u16,
+        argument_count: Arity,
         implementation: FunctionImplementation,
     },
 }
 
+// NaN is equal to itself, following the NaN policy described on
+// `builtins::equal`.
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Noop, Self::Noop) => true,
+            (Self::Boolean(a), Self::Boolean(b)) => a == b,
+            (Self::Integer(a), Self::Integer(b)) => a == b,
+            (Self::Float(a), Self::Float(b)) => a == b || a.is_nan() && b.is_nan(),
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::Char(a), Self::Char(b)) => a == b,
+            (Self::List(a), Self::List(b)) => a == b,
+            (Self::Map(a), Self::Map(b)) => a == b,
+            (Self::Error(a), Self::Error(b)) => a == b,
+            (
+                Self::Function {
+                    argument_count: our_count,
+                    implementation: our_impl,
+                },
+                Self::Function {
+                    argument_count: their_count,
+                    implementation: their_impl,
+                },
+            ) => our_count == their_count && our_impl == their_impl,
+            _ => false,
+        }
+    }
+}
+
+// Equality is a proper equivalence relation, since NaN equals itself.
+impl Eq for Object {}
+
+impl Hash for Object {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+
+        match self {
+            Self::Boolean(value) => value.hash(state),
+            Self::Integer(value) => value.hash(state),
+            Self::String(value) => value.hash(state),
+            Self::Char(value) => value.hash(state),
+            // Unhashable objects only hash their variant, which is still
+            // consistent with equality.
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Arity {
+    Exact(u16),
+    AtLeast(u16),
+    Variadic,
+}
+
+impl Arity {
+    // A compact form for signatures such as `<function/2>`.
+    pub fn abbreviated(&self) -> String {
+        match self {
+            Self::Exact(count) => count.to_string(),
+            Self::AtLeast(count) => format!("{}+", count),
+            Self::Variadic => "*".to_string(),
+        }
+    }
+
+    pub fn accepts(&self, count: usize) -> bool {
+        match self {
+            Self::Exact(expected) => count == *expected as usize,
+            Self::AtLeast(minimum) => count >= *minimum as usize,
+            Self::Variadic => true,
+        }
+    }
+
+    // The expected argument count with a noun that agrees with it, as in
+    // "1 argument" or "at least 2 arguments".
+    pub fn arguments(&self) -> String {
+        match self {
+            Self::Exact(1) | Self::AtLeast(1) => format!("{} argument", self),
+            _ => format!("{} arguments", self),
+        }
+    }
+}
+
+impl Display for Arity {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            Self::Exact(count) => write!(formatter, "{}", count),
+            Self::AtLeast(count) => write!(formatter, "at least {}", count),
+            Self::Variadic => write!(formatter, "any number of"),
+        }
+    }
+}
+
+// User-facing formatting: strings are unquoted and noop prints as nothing.
+// Floats print as the shortest decimal that reads back as the same value, never
+// in exponential notation, and always with a fractional part so that they can't
+// be mistaken for integers.
+fn format_float(value: f64) -> String {
+    let text = value.to_string();
+
+    if value.is_finite() && !text.contains('.') {
+        format!("{}.0", text)
+    } else {
+        text
+    }
+}
+
+impl Display for Object {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            Self::Noop => Ok(()),
+            Self::Boolean(value) => write!(formatter, "{}", value),
+            Self::Integer(value) => write!(formatter, "{}", value),
+            Self::Float(value) => write!(formatter, "{}", format_float(*value)),
+            Self::String(value) => write!(formatter, "{}", value),
+            Self::Char(value) => write!(formatter, "{}", value),
+            Self::List(items) => {
+                write!(formatter, "[")?;
+
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        write!(formatter, " ")?;
+                    }
+
+                    write!(formatter, "{}", item)?;
+                }
+
+                write!(formatter, "]")
+            }
+            Self::Map(entries) => {
+                // Sort the entries so that the output doesn't depend on the
+                // hash map's iteration order.
+                let mut entries: Vec<String> = entries
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key, value))
+                    .collect();
+                entries.sort();
+
+                write!(formatter, "{{{}}}", entries.join(", "))
+            }
+            Self::Error(message) => write!(formatter, "error: {}", message),
+            Self::Function { argument_count, .. } => {
+                write!(formatter, "<function/{}>", argument_count.abbreviated())
+            }
+        }
+    }
+}
+
 impl Object {
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Self::Noop | Self::Boolean(false))
+    }
+
     pub fn get_integer(&self) -> Option<i64> {
         match self {
             Self::Integer(value) => Some(*value),
@@ -27,17 +196,105 @@ impl Object {
             _ => None,
         }
     }
+
+    // Returns the value of either numeric type as a float.
+    pub fn get_number(&self) -> Option<f64> {
+        match self {
+            Self::Integer(value) => Some(*value as f64),
+            Self::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn is_number(&self) -> bool {
+        matches!(self, Self::Integer(_) | Self::Float(_))
+    }
+
+    pub fn get_string(&self) -> Option<&str> {
+        match self {
+            Self::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_char(&self) -> Option<char> {
+        match self {
+            Self::Char(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Noop => "noop",
+            Self::Boolean(_) => "boolean",
+            Self::Integer(_) => "integer",
+            Self::Float(_) => "float",
+            Self::String(_) => "string",
+            Self::Char(_) => "char",
+            Self::List(_) => "list",
+            Self::Map(_) => "map",
+            Self::Error(_) => "error",
+            Self::Function { .. } => "function",
+        }
+    }
+
+    // The type name with its indefinite article, e.g. `an integer`.
+    pub fn type_with_article(&self) -> String {
+        let name = self.type_name();
+
+        if name.starts_with(['a', 'e', 'i', 'o', 'u']) {
+            format!("an {}", name)
+        } else {
+            format!("a {}", name)
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(self, Self::Error(_))
+    }
+
+    pub fn get_list(&self) -> Option<&Vec<Object>> {
+        match self {
+            Self::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn get_map(&self) -> Option<&HashMap<Object, Object>> {
+        match self {
+            Self::Map(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    // Whether the object can be used as a map key.
+    pub fn is_hashable(&self) -> bool {
+        matches!(
+            self,
+            Self::Boolean(_) | Self::Integer(_) | Self::String(_) | Self::Char(_)
+        )
+    }
 }
 
+pub type Builtin = dyn Fn(Vec<Object>) -> EvaluationResult;
+pub type ContextBuiltin = dyn Fn(&mut Bloodbath, Vec<Object>) -> EvaluationResult;
+
 #[derive(Clone)]
 pub enum FunctionImplementation {
-    Builtin(Rc<dyn Fn(Vec<Object>) -> Object>),
+    Builtin(Rc<Builtin>),
+    ContextBuiltin(Rc<ContextBuiltin>),
+    User(Rc<UserFunction>),
+    // A user function calling itself. The reference is weak so that recursive
+    // functions don't keep themselves alive.
+    Recursive(Weak<UserFunction>),
 }
 
 impl Debug for FunctionImplementation {
     fn fmt(&self, formatter: &mut Formatter) -> Result<(), std::fmt::Error> {
         match self {
-            Self::Builtin(_) => write!(formatter, "<builtin>")?,
+            Self::Builtin(_) | Self::ContextBuiltin(_) => write!(formatter, "<builtin>")?,
+            Self::User(_) | Self::Recursive(_) => write!(formatter, "<function>")?,
         };
 
         Ok(())
@@ -46,19 +303,42 @@ impl Debug for FunctionImplementation {
 
 impl PartialEq for FunctionImplementation {
     fn eq(&self, other: &Self) -> bool {
-        match self {
-            Self::Builtin(our_impl) => match other {
-                Self::Builtin(their_impl) => Rc::ptr_eq(our_impl, their_impl),
-            },
+        match (self, other) {
+            (Self::Builtin(our_impl), Self::Builtin(their_impl)) => {
+                Rc::ptr_eq(our_impl, their_impl)
+            }
+            (Self::ContextBuiltin(our_impl), Self::ContextBuiltin(their_impl)) => {
+                Rc::ptr_eq(our_impl, their_impl)
+            }
+            (Self::User(our_impl), Self::User(their_impl)) => Rc::ptr_eq(our_impl, their_impl),
+            (Self::Recursive(our_impl), Self::Recursive(their_impl)) => {
+                Weak::ptr_eq(our_impl, their_impl)
+            }
+            (Self::User(strong), Self::Recursive(weak))
+            | (Self::Recursive(weak), Self::User(strong)) => Rc::as_ptr(strong) == weak.as_ptr(),
+            _ => false,
         }
     }
 }
 
 impl FunctionImplementation {
-    pub fn call(&self, arguments: Vec<Object>) -> Object {
-        match self {
+    pub fn call(&self, interpreter: &mut Bloodbath, arguments: Vec<Object>) -> EvaluationResult {
+        interpreter.enter_call()?;
+
+        let result = match self {
             FunctionImplementation::Builtin(action) => (action)(arguments),
-        }
+            FunctionImplementation::ContextBuiltin(action) => (action)(interpreter, arguments),
+            FunctionImplementation::User(function) => function.call(interpreter, arguments),
+            FunctionImplementation::Recursive(function) => match function.upgrade() {
+                Some(function) => function.call(interpreter, arguments),
+                None => Err(RuntimeError::InvalidArgument(
+                    "The function no longer exists".into(),
+                )),
+            },
+        };
+
+        interpreter.leave_call();
+        result
     }
 }
 
@@ -66,6 +346,130 @@ impl FunctionImplementation {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_arity() {
+        assert!(Arity::Exact(2).accepts(2));
+        assert!(!Arity::Exact(2).accepts(1));
+        assert!(!Arity::Exact(2).accepts(3));
+
+        assert!(Arity::AtLeast(1).accepts(1));
+        assert!(Arity::AtLeast(1).accepts(5));
+        assert!(!Arity::AtLeast(1).accepts(0));
+
+        assert!(Arity::Variadic.accepts(0));
+        assert!(Arity::Variadic.accepts(100));
+
+        assert_eq!(Arity::Exact(1).arguments(), "1 argument");
+        assert_eq!(Arity::Exact(2).arguments(), "2 arguments");
+        assert_eq!(Arity::AtLeast(1).arguments(), "at least 1 argument");
+        assert_eq!(Arity::AtLeast(2).arguments(), "at least 2 arguments");
+        assert_eq!(Arity::Variadic.arguments(), "any number of arguments");
+    }
+
+    #[test]
+    fn test_type_name() {
+        assert_eq!(Object::Noop.type_name(), "noop");
+        assert_eq!(Object::Boolean(true).type_name(), "boolean");
+        assert_eq!(Object::Integer(1).type_name(), "integer");
+        assert_eq!(Object::Float(1.0).type_name(), "float");
+        assert_eq!(Object::String("".into()).type_name(), "string");
+        assert_eq!(Object::Char('a').type_name(), "char");
+        assert_eq!(Object::List(vec![].into()).type_name(), "list");
+        assert_eq!(Object::Map(HashMap::new().into()).type_name(), "map");
+        assert_eq!(Object::Error("".into()).type_name(), "error");
+
+        let function = Object::Function {
+            argument_count: Arity::Exact(0),
+            implementation: FunctionImplementation::Builtin(Rc::new(|_| Ok(Object::Noop))),
+        };
+
+        assert_eq!(function.type_name(), "function");
+
+        assert_eq!(Object::Integer(1).type_with_article(), "an integer");
+        assert_eq!(Object::Error("".into()).type_with_article(), "an error");
+        assert_eq!(Object::Float(1.0).type_with_article(), "a float");
+        assert_eq!(Object::Noop.type_with_article(), "a noop");
+    }
+
+    #[test]
+    fn test_get_number() {
+        assert_eq!(Object::Integer(2).get_number(), Some(2.0));
+        assert_eq!(Object::Float(0.5).get_number(), Some(0.5));
+        assert!(Object::Float(f64::NAN).get_number().unwrap().is_nan());
+
+        assert_eq!(Object::Noop.get_number(), None);
+        assert_eq!(Object::Boolean(true).get_number(), None);
+        assert_eq!(Object::String("1".into()).get_number(), None);
+        assert_eq!(Object::Char('1').get_number(), None);
+        assert_eq!(
+            Object::List(vec![Object::Integer(1)].into()).get_number(),
+            None
+        );
+        assert_eq!(Object::Error("".into()).get_number(), None);
+
+        assert!(Object::Integer(1).is_number());
+        assert!(Object::Float(1.0).is_number());
+        assert!(!Object::String("1".into()).is_number());
+    }
+
+    #[test]
+    fn test_truthiness() {
+        assert!(!Object::Noop.is_truthy());
+        assert!(!Object::Boolean(false).is_truthy());
+
+        assert!(Object::Boolean(true).is_truthy());
+        assert!(Object::Integer(0).is_truthy());
+        assert!(Object::String("".into()).is_truthy());
+        assert!(Object::List(vec![].into()).is_truthy());
+    }
+
+    #[test]
+    fn test_display_float() {
+        assert_eq!(Object::Float(2.0).to_string(), "2.0");
+        assert_eq!(Object::Float(-0.0).to_string(), "-0.0");
+        assert_eq!(Object::Float(0.1).to_string(), "0.1");
+        assert_eq!(Object::Float(0.1 + 0.2).to_string(), "0.30000000000000004");
+        assert_eq!(Object::Float(1e-7).to_string(), "0.0000001");
+        assert_eq!(Object::Float(1e21).to_string(), "1000000000000000000000.0");
+
+        assert_eq!(Object::Float(f64::NAN).to_string(), "NaN");
+        assert_eq!(Object::Float(f64::NEG_INFINITY).to_string(), "-inf");
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(format!("{}", Object::Noop), "");
+        assert_eq!(format!("{}", Object::Boolean(true)), "true");
+        assert_eq!(format!("{}", Object::Integer(-42)), "-42");
+        assert_eq!(format!("{}", Object::Float(2.5)), "2.5");
+        assert_eq!(format!("{}", Object::String("a \"b\"".into())), "a \"b\"");
+        assert_eq!(format!("{}", Object::Error("boom".into())), "error: boom");
+
+        assert_eq!(
+            format!(
+                "{}",
+                Object::List(
+                    vec![
+                        Object::Integer(1),
+                        Object::String("two".into()),
+                        Object::List(vec![].into()),
+                    ]
+                    .into()
+                )
+            ),
+            "[1 two []]"
+        );
+
+        let function = |argument_count| Object::Function {
+            argument_count,
+            implementation: FunctionImplementation::Builtin(Rc::new(|_| Ok(Object::Noop))),
+        };
+
+        assert_eq!(format!("{}", function(Arity::Exact(2))), "<function/2>");
+        assert_eq!(format!("{}", function(Arity::AtLeast(1))), "<function/1+>");
+        assert_eq!(format!("{}", function(Arity::Variadic)), "<function/*>");
+    }
+
     #[test]
     fn test_integer() {
         let a = Object::Integer(1);
@@ -88,15 +492,34 @@ mod tests {
         assert!((a_value + b_value - 3.0).abs() <= 1e-3);
     }
 
+    #[test]
+    fn test_string() {
+        let string = Object::String("hello".into());
+
+        assert_eq!(string.get_string(), Some("hello"));
+        assert_eq!(Object::Integer(1).get_string(), None);
+    }
+
+    #[test]
+    fn test_list() {
+        let list = Object::List(vec![Object::Integer(1), Object::Float(2.0)].into());
+        let items = list.get_list().unwrap();
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0], Object::Integer(1));
+
+        assert_eq!(Object::Integer(1).get_list(), None);
+    }
+
     #[test]
     fn test_builtin_function() {
         let function = Object::Function {
-            argument_count: 1,
+            argument_count: Arity::Exact(1),
             implementation: FunctionImplementation::Builtin(Rc::new(|args| {
                 assert_eq!(args.len(), 1);
 
                 match args[0] {
-                    Object::Integer(x) => Object::Integer(x + 1),
+                    Object::Integer(x) => Ok(Object::Integer(x + 1)),
                     _ => unreachable!(),
                 }
             })),
@@ -110,10 +533,57 @@ mod tests {
                 let sixty_eight = Object::Integer(68);
                 let sixty_nine = Object::Integer(69);
 
-                assert_eq!(argument_count, 1);
-                assert_eq!(implementation.call(vec![sixty_eight]), sixty_nine);
+                assert_eq!(argument_count, Arity::Exact(1));
+                assert_eq!(
+                    implementation.call(&mut Bloodbath::new(), vec![sixty_eight]),
+                    Ok(sixty_nine)
+                );
             }
             _ => unreachable!(),
         }
     }
+
+    #[test]
+    fn test_context_builtin() {
+        let implementation =
+            FunctionImplementation::ContextBuiltin(Rc::new(|interpreter, args| {
+                interpreter.variable_set("seen", args[0].clone());
+                Ok(Object::Noop)
+            }));
+
+        let mut bloodbath = Bloodbath::new();
+        implementation
+            .call(&mut bloodbath, vec![Object::Integer(3)])
+            .unwrap();
+
+        assert_eq!(
+            bloodbath.variable_get("seen").as_deref(),
+            Some(&Object::Integer(3))
+        );
+        assert_eq!(implementation, implementation.clone());
+    }
+
+    #[test]
+    fn test_hashing() {
+        let mut map = HashMap::new();
+        map.insert(Object::Integer(1), Object::String("one".into()));
+        map.insert(Object::String("1".into()), Object::Integer(1));
+
+        assert_eq!(
+            map.get(&Object::Integer(1)),
+            Some(&Object::String("one".into()))
+        );
+        assert_eq!(
+            map.get(&Object::String("1".into())),
+            Some(&Object::Integer(1))
+        );
+        assert_eq!(map.get(&Object::Char('1')), None);
+
+        assert!(Object::Integer(1).is_hashable());
+        assert!(Object::String("".into()).is_hashable());
+        assert!(!Object::Float(1.0).is_hashable());
+        assert!(!Object::List(vec![].into()).is_hashable());
+
+        assert_eq!(Object::Map(map.into()).to_string(), "{1: 1, 1: one}");
+    }
 }
@@ -0,0 +1,117 @@
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+// A xorshift64* generator. Not suitable for anything security-related, but
+// small, fast and fully reproducible under a fixed seed.
+#[derive(Debug, Clone)]
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        let mut us = Self { state: 0 };
+        us.seed(seed);
+        us
+    }
+
+    pub fn from_time() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_nanos() as u64)
+            .unwrap_or(0);
+
+        Self::new(seed)
+    }
+
+    pub fn seed(&mut self, seed: u64) {
+        // The state must never be zero, otherwise the generator gets stuck.
+        self.state = seed ^ 0x9E37_79B9_7F4A_7C15;
+
+        if self.state == 0 {
+            self.state = 1;
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // Returns a number in `0..bound`. `bound` must be non-zero.
+    pub fn next_below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    // Returns a number in `0.0..1.0`.
+    pub fn next_float(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Returns a number in `low..=high`. `low` must not be greater than `high`.
+    pub fn next_in_range(&mut self, low: i64, high: i64) -> i64 {
+        let span = high.wrapping_sub(low) as u64;
+
+        let offset = match span.checked_add(1) {
+            Some(bound) => self.next_below(bound),
+            None => self.next_u64(),
+        };
+
+        low.wrapping_add(offset as i64)
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for index in (1..items.len()).rev() {
+            let other = self.next_below(index as u64 + 1) as usize;
+            items.swap(index, other);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reproducible() {
+        let mut a = Random::new(42);
+        let mut b = Random::new(42);
+
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+
+        for _ in 0..100 {
+            assert!(a.next_below(7) < 7);
+        }
+    }
+
+    #[test]
+    fn test_ranges() {
+        let mut random = Random::new(7);
+
+        for _ in 0..1000 {
+            let value = random.next_float();
+            assert!((0.0..1.0).contains(&value));
+
+            let value = random.next_in_range(-3, 3);
+            assert!((-3..=3).contains(&value));
+        }
+
+        assert_eq!(random.next_in_range(5, 5), 5);
+        random.next_in_range(i64::MIN, i64::MAX);
+    }
+
+    #[test]
+    fn test_shuffle() {
+        let mut items: Vec<i64> = (0..20).collect();
+        Random::new(1).shuffle(&mut items);
+
+        assert_ne!(items, (0..20).collect::<Vec<i64>>());
+
+        items.sort();
+        assert_eq!(items, (0..20).collect::<Vec<i64>>());
+    }
+}
@@ -1,123 +1,390 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::io::BufReader;
+use std::io::Bytes;
+use std::io::Read;
+
 #[derive(Debug, PartialEq)]
 pub enum Token {
     Identifier(String),
     IntegerConstant(i64),
     FloatConstant(f64),
+    StringConstant(String),
+    CharConstant(char),
+    Boolean(bool),
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
+    Terminator,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum ReaderError {
     EoF,
     UnexpectedCharacter(char),
+    UnterminatedString,
+    UnterminatedCharacter,
+    UnknownEscape(char),
+    // The number read so far, the offending character and its position.
+    ExpectedADigit(String, char, usize),
+    // Like `ExpectedADigit`, but the input ended instead, as in `1.`.
+    ExpectedADigitAtEoF(String),
+    NumberOutOfRange(String),
+    MisplacedUnderscore,
+    IoFailed(String),
+}
+
+impl Display for ReaderError {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            Self::EoF => write!(formatter, "Unexpected end of file"),
+            Self::UnexpectedCharacter(bad_char) => {
+                write!(formatter, "Unexpected character: '{}'", bad_char)
+            }
+            Self::UnterminatedString => write!(formatter, "Unterminated string literal"),
+            Self::UnterminatedCharacter => write!(formatter, "Unterminated character literal"),
+            Self::UnknownEscape(bad_char) => {
+                write!(formatter, "Unknown escape sequence: '\\{}'", bad_char)
+            }
+            Self::ExpectedADigit(number, bad_char, position) => write!(
+                formatter,
+                "Expected a digit after '{}', found '{}' at position {}",
+                number, bad_char, position
+            ),
+            Self::ExpectedADigitAtEoF(number) => write!(
+                formatter,
+                "Expected a digit after '{}', found the end of input",
+                number
+            ),
+            Self::NumberOutOfRange(literal) => {
+                write!(formatter, "Integer literal {} is out of range", literal)
+            }
+            Self::MisplacedUnderscore => {
+                write!(formatter, "`_` in a number must be between two digits")
+            }
+            Self::IoFailed(message) => write!(formatter, "IO error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ReaderError {}
+
+type Characters<'a> = Box<dyn Iterator<Item = Result<char, ReaderError>> + 'a>;
+
+// Decodes UTF-8 one character at a time, so that a source never has to be read
+// into memory all at once.
+struct Utf8Characters<R: Read> {
+    bytes: Bytes<BufReader<R>>,
+}
+
+impl<R: Read> Utf8Characters<R> {
+    fn next_byte(&mut self) -> Result<Option<u8>, ReaderError> {
+        self.bytes
+            .next()
+            .transpose()
+            .map_err(|err| ReaderError::IoFailed(err.to_string()))
+    }
+
+    fn next_character(&mut self) -> Result<Option<char>, ReaderError> {
+        let invalid = || ReaderError::IoFailed("input is not valid UTF-8".into());
+
+        let first = match self.next_byte()? {
+            Some(first) => first,
+            None => return Ok(None),
+        };
+
+        let width = match first {
+            0x00..=0x7F => 1,
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => return Err(invalid()),
+        };
+
+        let mut buffer = [first, 0, 0, 0];
+
+        for byte in buffer.iter_mut().take(width).skip(1) {
+            *byte = self.next_byte()?.ok_or_else(invalid)?;
+        }
+
+        match std::str::from_utf8(&buffer[..width]) {
+            Ok(decoded) => Ok(decoded.chars().next()),
+            Err(_) => Err(invalid()),
+        }
+    }
+}
+
+impl<R: Read> Iterator for Utf8Characters<R> {
+    type Item = Result<char, ReaderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_character().transpose()
+    }
+}
+
+fn is_separator(input: &char) -> bool {
+    input.is_whitespace()
 }
 
-pub struct Reader {
-    input: String,
+// `;` ends an expression explicitly, e.g. `set a 1; a`.
+fn is_delimiter(input: &char) -> bool {
+    is_separator(input) || ['{', '}', '[', ']', ';'].contains(input)
+}
+
+// Running out of input inside a string literal means it was never terminated.
+fn unterminated_string(err: ReaderError) -> ReaderError {
+    match err {
+        ReaderError::EoF => ReaderError::UnterminatedString,
+        err => err,
+    }
+}
+
+pub struct Reader<'a> {
+    source: Characters<'a>,
+    // Characters taken from `source` but not consumed yet. The first one is
+    // the current character.
+    lookahead: VecDeque<Result<char, ReaderError>>,
+    // How many characters have been consumed, i.e. the current one's index.
     position: usize,
+    // Whether a line break came before the token read last.
+    line_break: bool,
 }
 
-impl Reader {
-    pub fn new(input: String) -> Self {
-        Self { input, position: 0 }
+impl<'a> Reader<'a> {
+    // Borrows the input and reads it in place, without copying it.
+    pub fn new(input: &'a str) -> Self {
+        Self::from_characters(Box::new(input.chars().map(Ok)))
     }
 
-    fn peek(&self, amount: usize) -> Result<char, ReaderError> {
-        let position = self.position + amount;
-        self.input.chars().nth(position).ok_or(ReaderError::EoF)
+    // Reads from any source, decoding it as UTF-8 along the way.
+    pub fn from_reader<R: Read + 'a>(source: R) -> Self {
+        Self::from_characters(Box::new(Utf8Characters {
+            bytes: BufReader::new(source).bytes(),
+        }))
     }
 
-    fn current(&self) -> Result<char, ReaderError> {
+    fn from_characters(source: Characters<'a>) -> Self {
+        Self {
+            source,
+            lookahead: VecDeque::new(),
+            position: 0,
+            line_break: false,
+        }
+    }
+
+    fn peek(&mut self, amount: usize) -> Result<char, ReaderError> {
+        while self.lookahead.len() <= amount {
+            match self.source.next() {
+                Some(character) => self.lookahead.push_back(character),
+                None => return Err(ReaderError::EoF),
+            }
+        }
+
+        self.lookahead[amount].clone()
+    }
+
+    fn current(&mut self) -> Result<char, ReaderError> {
         self.peek(0)
     }
 
-    fn next(&mut self) -> Result<char, ReaderError> {
-        self.position += 1;
+    fn advance(&mut self) -> Result<char, ReaderError> {
+        if self.lookahead.pop_front().is_some() {
+            self.position += 1;
+        }
+
         self.current()
     }
 
     fn next_or_eof(&mut self) -> Result<bool, ReaderError> {
-        match self.next() {
+        match self.advance() {
             Err(ReaderError::EoF) => Ok(true),
             Err(err) => Err(err),
             Ok(_) => Ok(false),
         }
     }
 
-    fn is_eof(&self) -> bool {
-        self.position >= self.input.len()
+    fn is_eof(&mut self) -> bool {
+        self.current() == Err(ReaderError::EoF)
+    }
+
+    // Numbers look like `1`, `-1`, `1.5`, `.5` or `-.5`.
+    fn starts_number(&mut self) -> bool {
+        let next: Vec<Option<char>> = (0..3).map(|amount| self.peek(amount).ok()).collect();
+
+        let is_digit_at =
+            |amount: usize| matches!(next[amount], Some(next) if next.is_ascii_digit());
+
+        let unsigned_at = |amount: usize| {
+            is_digit_at(amount) || next[amount] == Some('.') && is_digit_at(amount + 1)
+        };
+
+        unsigned_at(0) || next[0] == Some('-') && unsigned_at(1)
     }
 
-    fn is_separator(&self, input: &char) -> bool {
-        [' ', '\t', '\n', '\r', '\t'].contains(input)
+    // Digits may be grouped with underscores, as in `1_000`, which are dropped.
+    fn read_digits(&mut self) -> Result<String, ReaderError> {
+        let mut digits = String::new();
+
+        while !self.is_eof() {
+            let current = self.current()?;
+
+            if current.is_ascii_digit() {
+                digits.push(current);
+            } else if current == '_' {
+                let is_followed_by_digit =
+                    matches!(self.peek(1), Ok(next) if next.is_ascii_digit());
+
+                if digits.is_empty() || !is_followed_by_digit {
+                    return Err(ReaderError::MisplacedUnderscore);
+                }
+            } else {
+                break;
+            }
+
+            self.next_or_eof()?;
+        }
+
+        Ok(digits)
     }
 
     fn read_number(&mut self) -> Result<Token, ReaderError> {
         let sign = if self.current()? == '-' {
-            self.next()?;
+            self.advance()?;
             -1
         } else {
             1
         };
 
-        let mut whole = 0;
+        let whole = self.read_digits()?;
+        let mut fractional = None;
+
+        // What has been read so far, for error messages.
+        let mut partial = if sign < 0 {
+            format!("-{}", whole)
+        } else {
+            whole.clone()
+        };
 
-        let mut fractional: Option<i64> = None;
-        let mut fractional_multiplier = 1;
+        if !self.is_eof() && self.current()? == '.' {
+            self.next_or_eof()?;
+            partial.push('.');
 
-        while !self.is_separator(&self.current()?) && self.current()? != '.' {
-            let digit = self.current()? as i64 - '0' as i64;
+            let digits = self.read_digits()?;
 
-            if digit < 0 || digit > 9 {
-                return Ok(Token::IntegerConstant(sign * whole));
+            // At least one digit must follow the decimal point.
+            if digits.is_empty() {
+                return Err(self.expected_a_digit(partial));
             }
 
-            whole *= 10;
-            whole += digit;
+            partial.push_str(&digits);
+            fractional = Some(digits);
+        }
 
-            if self.next_or_eof()? {
-                return Ok(Token::IntegerConstant(sign * whole));
+        // A number must end right where the next token begins, so things like
+        // `1.2.3` or `12x` are rejected instead of being split in two.
+        if !self.is_eof() && !is_delimiter(&self.current()?) {
+            return Err(self.expected_a_digit(partial));
+        }
+
+        if let Some(fractional) = fractional {
+            let value: f64 = format!("{}.{}", whole, fractional).parse().unwrap();
+            Ok(Token::FloatConstant(sign as f64 * value))
+        } else {
+            // Parse the sign along with the digits so that `i64::MIN` fits.
+            let literal = if sign < 0 {
+                format!("-{}", whole)
+            } else {
+                whole
+            };
+
+            match literal.parse() {
+                Ok(value) => Ok(Token::IntegerConstant(value)),
+                Err(_) => Err(ReaderError::NumberOutOfRange(literal)),
             }
         }
+    }
+
+    fn expected_a_digit(&mut self, partial: String) -> ReaderError {
+        match self.current() {
+            Ok(found) => ReaderError::ExpectedADigit(partial, found, self.position),
+            Err(ReaderError::EoF) => ReaderError::ExpectedADigitAtEoF(partial),
+            Err(err) => err,
+        }
+    }
 
-        if self.current()? == '.' {
-            self.next()?;
+    fn read_escape(&mut self) -> Result<char, ReaderError> {
+        let escaped = self.advance()?;
 
-            fractional = Some(0);
+        match escaped {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            '0' => Ok('\0'),
+            '\\' | '"' | '\'' => Ok(escaped),
+            _ => Err(ReaderError::UnknownEscape(escaped)),
+        }
+    }
 
-            while !self.is_separator(&self.current()?) {
-                let digit = self.current()? as i64 - '0' as i64;
+    fn read_string(&mut self) -> Result<Token, ReaderError> {
+        let mut string = String::new();
 
-                if digit < 0 || digit > 9 {
-                    let fractional = (fractional.unwrap() / fractional_multiplier) as f64;
+        loop {
+            let character = self.advance().map_err(unterminated_string)?;
 
-                    return Ok(Token::FloatConstant(
-                        sign as f64 * (whole as f64 + fractional),
-                    ));
+            match character {
+                '"' => {
+                    self.next_or_eof()?;
+                    return Ok(Token::StringConstant(string));
                 }
+                '\\' => string.push(self.read_escape().map_err(unterminated_string)?),
+                _ => string.push(character),
+            }
+        }
+    }
 
-                fractional = Some(fractional.unwrap() * 10 + digit);
-                fractional_multiplier *= 10;
+    // Raw strings look like `r"C:\path"` and take backslashes literally, so
+    // they can't contain a `"`.
+    fn read_raw_string(&mut self) -> Result<Token, ReaderError> {
+        let mut string = String::new();
 
-                if self.next_or_eof()? {
-                    break;
+        // Skip the `r`; the loop below skips the opening quote.
+        self.advance()?;
+
+        loop {
+            match self.advance().map_err(unterminated_string)? {
+                '"' => {
+                    self.next_or_eof()?;
+                    return Ok(Token::StringConstant(string));
                 }
+                character => string.push(character),
             }
         }
+    }
 
-        if let Some(fractional) = fractional {
-            let fractional = fractional as f64 / fractional_multiplier as f64;
+    // Character literals look like `'a'` and support the same escapes as strings.
+    fn read_character(&mut self) -> Result<Token, ReaderError> {
+        let unterminated = |err| match err {
+            ReaderError::EoF => ReaderError::UnterminatedCharacter,
+            err => err,
+        };
 
-            Ok(Token::FloatConstant(
-                sign as f64 * (whole as f64 + fractional),
-            ))
-        } else {
-            Ok(Token::IntegerConstant(sign * whole))
+        let character = match self.advance().map_err(unterminated)? {
+            '\\' => self.read_escape().map_err(unterminated)?,
+            '\'' => return Err(ReaderError::UnexpectedCharacter('\'')),
+            character => character,
+        };
+
+        if self.advance().map_err(unterminated)? != '\'' {
+            return Err(ReaderError::UnterminatedCharacter);
         }
+
+        self.next_or_eof()?;
+        Ok(Token::CharConstant(character))
     }
 
-    fn read_identifier(&mut self) -> Result<Token, ReaderError> {
+    fn read_identifier(&mut self) -> Result<String, ReaderError> {
         let mut identifier = String::new();
 
         loop {
@@ -145,8 +412,8 @@ impl Reader {
             }
 
             if !is_legal {
-                if self.current()? == '{' || self.current()? == '}' {
-                    return Ok(Token::Identifier(identifier));
+                if is_delimiter(&self.current()?) {
+                    return Ok(identifier);
                 } else {
                     return Err(ReaderError::UnexpectedCharacter(self.current()?));
                 }
@@ -154,14 +421,41 @@ impl Reader {
 
             identifier.push(self.current()?);
 
-            if self.next_or_eof()? || self.is_separator(&self.current()?) {
-                return Ok(Token::Identifier(identifier));
+            if self.next_or_eof()? || is_separator(&self.current()?) {
+                return Ok(identifier);
             }
+
+            // Identifiers made up only of symbols, like `+` or `<=`, end where a
+            // digit begins, so `+1 2` reads the same as `+ 1 2`.
+            let is_symbolic = identifier
+                .chars()
+                .all(|character| !character.is_ascii_alphanumeric());
+
+            if is_symbolic && self.current()?.is_ascii_digit() {
+                return Ok(identifier);
+            }
+        }
+    }
+
+    // Boolean literals are reserved words, so they can never be shadowed.
+    fn read_word(&mut self) -> Result<Token, ReaderError> {
+        let identifier = self.read_identifier()?;
+
+        if identifier == "true" {
+            Ok(Token::Boolean(true))
+        } else if identifier == "false" {
+            Ok(Token::Boolean(false))
+        } else {
+            Ok(Token::Identifier(identifier))
         }
     }
 
     fn skip_separators(&mut self) -> Result<(), ReaderError> {
-        while self.is_separator(&self.current()?) {
+        while is_separator(&self.current()?) {
+            if self.current()? == '\n' {
+                self.line_break = true;
+            }
+
             if self.next_or_eof()? {
                 break;
             }
@@ -170,42 +464,171 @@ impl Reader {
         Ok(())
     }
 
-    pub fn tokenise(&mut self) -> Result<Vec<Token>, ReaderError> {
-        let mut tokens = Vec::new();
+    // Reads the next token, or returns `None` once the input is exhausted.
+    pub fn next_token(&mut self) -> Result<Option<Token>, ReaderError> {
+        self.line_break = false;
 
-        while !self.is_eof() {
+        if !self.is_eof() {
             self.skip_separators()?;
+        }
 
-            if ('0'..='9').contains(&self.current()?)
-                || self.current()? == '-' && ('0'..='9').contains(&self.peek(1)?)
-            {
-                tokens.push(self.read_number()?);
-            } else if self.current()? == '{' {
-                tokens.push(Token::LeftBrace);
-                self.next_or_eof()?;
-            } else if self.current()? == '}' {
-                tokens.push(Token::RightBrace);
-                self.next_or_eof()?;
-            } else {
-                tokens.push(self.read_identifier()?);
-            }
+        if self.is_eof() {
+            return Ok(None);
+        }
+
+        let token = if self.starts_number() {
+            self.read_number()?
+        } else if self.current()? == '"' {
+            self.read_string()?
+        } else if self.current()? == 'r' && self.peek(1) == Ok('"') {
+            self.read_raw_string()?
+        } else if self.current()? == '\'' {
+            self.read_character()?
+        } else if self.current()? == '{' {
+            self.next_or_eof()?;
+            Token::LeftBrace
+        } else if self.current()? == '}' {
+            self.next_or_eof()?;
+            Token::RightBrace
+        } else if self.current()? == '[' {
+            self.next_or_eof()?;
+            Token::LeftBracket
+        } else if self.current()? == ']' {
+            self.next_or_eof()?;
+            Token::RightBracket
+        } else if self.current()? == ';' {
+            self.next_or_eof()?;
+            Token::Terminator
+        } else {
+            self.read_word()?
+        };
+
+        Ok(Some(token))
+    }
 
-            if !self.is_eof() {
-                self.skip_separators()?;
+    pub fn tokenise(&mut self) -> Result<Vec<Token>, ReaderError> {
+        self.collect()
+    }
+}
+
+impl Iterator for Reader<'_> {
+    type Item = Result<Token, ReaderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_token().transpose()
+    }
+}
+
+// Reads tokens lazily, one at a time, with a single token of lookahead.
+pub struct TokenStream<'a> {
+    reader: Reader<'a>,
+    peeked: Option<Token>,
+}
+
+impl<'a> From<Reader<'a>> for TokenStream<'a> {
+    fn from(reader: Reader<'a>) -> Self {
+        Self {
+            reader,
+            peeked: None,
+        }
+    }
+}
+
+impl<'a> TokenStream<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self::from(Reader::new(input))
+    }
+
+    pub fn peek(&mut self) -> Result<Option<&Token>, ReaderError> {
+        if self.peeked.is_none() {
+            self.peeked = self.reader.next_token()?;
+        }
+
+        Ok(self.peeked.as_ref())
+    }
+
+    pub fn is_empty(&mut self) -> Result<bool, ReaderError> {
+        Ok(self.peek()?.is_none())
+    }
+
+    // Whether the next token is the first on its line.
+    pub fn starts_line(&mut self) -> Result<bool, ReaderError> {
+        self.peek()?;
+        Ok(self.reader.line_break)
+    }
+
+    // Takes the next token, failing with `ReaderError::EoF` if there is none.
+    pub fn next_token(&mut self) -> Result<Token, ReaderError> {
+        self.peek()?;
+        self.peeked.take().ok_or(ReaderError::EoF)
+    }
+}
+
+// Checks whether `input` has balanced braces and brackets and no unterminated
+// string, i.e. whether it is worth handing over to the parser yet.
+pub fn is_complete(input: &str) -> bool {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut raw = false;
+    let mut escaped = false;
+    let mut raw_prefix = false;
+
+    let mut characters = input.chars().peekable();
+    let mut previous = None;
+
+    while let Some(character) = characters.next() {
+        let starts_token = previous.is_none_or(|previous| is_delimiter(&previous));
+        previous = Some(character);
+
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if character == '\\' && !raw {
+                escaped = true;
+            } else if character == '"' {
+                in_string = false;
             }
+
+            continue;
         }
 
-        Ok(tokens)
+        // Whether this character follows the `r` of a raw string.
+        let after_r = raw_prefix;
+        raw_prefix = false;
+
+        match character {
+            '"' => {
+                in_string = true;
+                raw = after_r;
+            }
+            'r' if starts_token => raw_prefix = true,
+            // Skip character literals so that `'{'` isn't counted as a brace.
+            '\'' if starts_token => {
+                if characters.next() == Some('\\') {
+                    characters.next();
+                }
+
+                characters.next_if_eq(&'\'');
+            }
+            '{' | '[' => depth += 1,
+            '}' | ']' => depth -= 1,
+            _ => {}
+        }
     }
+
+    // Excess closing braces can't be fixed by reading more input, so let the
+    // parser report them.
+    !in_string && depth <= 0
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Cursor;
 
     #[test]
     fn test_reader() {
-        let tokens = Reader::new("+ + 0.5 -1.0 -1".into()).tokenise().unwrap();
+        let tokens = Reader::new("+ + 0.5 -1.0 -1").tokenise().unwrap();
 
         assert_eq!(tokens.len(), 5);
 
@@ -224,7 +647,7 @@ mod tests {
             _ => unreachable!(),
         }
 
-        let tokens = Reader::new("set a noop set b a".into()).tokenise().unwrap();
+        let tokens = Reader::new("set a noop set b a").tokenise().unwrap();
 
         assert_eq!(tokens.len(), 6);
 
@@ -232,4 +655,529 @@ mod tests {
             assert_eq!(tokens[index], Token::Identifier(expected_value.to_string()));
         }
     }
+
+    #[test]
+    fn test_brackets() {
+        let tokens = Reader::new("[1 a][]").tokenise().unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::LeftBracket,
+                Token::IntegerConstant(1),
+                Token::Identifier("a".into()),
+                Token::RightBracket,
+                Token::LeftBracket,
+                Token::RightBracket,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strings() {
+        let tokens = Reader::new(r#""foo" "" "a b\n\"c\"""#).tokenise().unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::StringConstant("foo".into()),
+                Token::StringConstant("".into()),
+                Token::StringConstant("a b\n\"c\"".into()),
+            ]
+        );
+
+        assert_eq!(
+            Reader::new(r#"["x"]"#).tokenise(),
+            Ok(vec![
+                Token::LeftBracket,
+                Token::StringConstant("x".into()),
+                Token::RightBracket,
+            ])
+        );
+
+        assert_eq!(
+            Reader::new(r#""foo"#).tokenise(),
+            Err(ReaderError::UnterminatedString)
+        );
+
+        assert_eq!(
+            Reader::new(r#""foo\"#).tokenise(),
+            Err(ReaderError::UnterminatedString)
+        );
+
+        assert_eq!(
+            Reader::new(r#""\q""#).tokenise(),
+            Err(ReaderError::UnknownEscape('q'))
+        );
+    }
+
+    #[test]
+    fn test_raw_strings() {
+        assert_eq!(
+            Reader::new(r#"r"\n" "\n""#).tokenise(),
+            Ok(vec![
+                Token::StringConstant("\\n".into()),
+                Token::StringConstant("\n".into()),
+            ])
+        );
+
+        assert_eq!(
+            Reader::new(r#"[r"C:\path\" r"" r"\q"]"#).tokenise(),
+            Ok(vec![
+                Token::LeftBracket,
+                Token::StringConstant("C:\\path\\".into()),
+                Token::StringConstant("".into()),
+                Token::StringConstant("\\q".into()),
+                Token::RightBracket,
+            ])
+        );
+
+        assert_eq!(
+            Reader::new("r rest").tokenise(),
+            Ok(vec![
+                Token::Identifier("r".into()),
+                Token::Identifier("rest".into()),
+            ])
+        );
+
+        assert_eq!(
+            Reader::new(r#"r"foo"#).tokenise(),
+            Err(ReaderError::UnterminatedString)
+        );
+    }
+
+    #[test]
+    fn test_minus() {
+        assert_eq!(
+            Reader::new("- 5 3").tokenise(),
+            Ok(vec![
+                Token::Identifier("-".into()),
+                Token::IntegerConstant(5),
+                Token::IntegerConstant(3),
+            ])
+        );
+
+        assert_eq!(
+            Reader::new("-").tokenise(),
+            Ok(vec![Token::Identifier("-".into())])
+        );
+
+        assert_eq!(
+            Reader::new("set a -").tokenise(),
+            Ok(vec![
+                Token::Identifier("set".into()),
+                Token::Identifier("a".into()),
+                Token::Identifier("-".into()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_malformed_numbers() {
+        assert_eq!(
+            Reader::new("1.2.3").tokenise(),
+            Err(ReaderError::ExpectedADigit("1.2".into(), '.', 3))
+        );
+
+        assert_eq!(
+            Reader::new("1..2").tokenise(),
+            Err(ReaderError::ExpectedADigit("1.".into(), '.', 2))
+        );
+
+        assert_eq!(
+            Reader::new("1.").tokenise(),
+            Err(ReaderError::ExpectedADigitAtEoF("1.".into()))
+        );
+        assert_eq!(
+            Reader::new("[-2.").tokenise(),
+            Err(ReaderError::ExpectedADigitAtEoF("-2.".into()))
+        );
+
+        assert_eq!(
+            Reader::new("1. 2").tokenise(),
+            Err(ReaderError::ExpectedADigit("1.".into(), ' ', 2))
+        );
+
+        assert_eq!(
+            Reader::new("12x3").tokenise(),
+            Err(ReaderError::ExpectedADigit("12".into(), 'x', 2))
+        );
+
+        assert_eq!(
+            Reader::new("a -1.2y").tokenise(),
+            Err(ReaderError::ExpectedADigit("-1.2".into(), 'y', 6))
+        );
+
+        assert_eq!(
+            Reader::new("123456789012345678901234567890").tokenise(),
+            Err(ReaderError::NumberOutOfRange(
+                "123456789012345678901234567890".into()
+            ))
+        );
+
+        assert_eq!(
+            Reader::new("9223372036854775808").tokenise(),
+            Err(ReaderError::NumberOutOfRange("9223372036854775808".into()))
+        );
+
+        assert_eq!(
+            Reader::new("9223372036854775807 -9223372036854775808").tokenise(),
+            Ok(vec![
+                Token::IntegerConstant(i64::MAX),
+                Token::IntegerConstant(i64::MIN),
+            ])
+        );
+
+        assert_eq!(
+            Reader::new("123456789012345678901234567890.5").tokenise(),
+            Ok(vec![Token::FloatConstant(123456789012345678901234567890.5)])
+        );
+
+        assert_eq!(
+            Reader::new("1_000 1_000.5 1.234_567 -1_0").tokenise(),
+            Ok(vec![
+                Token::IntegerConstant(1000),
+                Token::FloatConstant(1000.5),
+                Token::FloatConstant(1.234567),
+                Token::IntegerConstant(-10),
+            ])
+        );
+
+        for input in ["1_", "1__0", "1_.5", "1._5", "1.5_"] {
+            assert_eq!(
+                Reader::new(input).tokenise(),
+                Err(ReaderError::MisplacedUnderscore),
+                "{}",
+                input
+            );
+        }
+
+        assert_eq!(
+            Reader::new("{1.25}").tokenise(),
+            Ok(vec![
+                Token::LeftBrace,
+                Token::FloatConstant(1.25),
+                Token::RightBrace,
+            ])
+        );
+    }
+
+    #[test]
+    fn test_leading_decimal_point() {
+        assert_eq!(
+            Reader::new(".5 -.5 -.25]").tokenise(),
+            Ok(vec![
+                Token::FloatConstant(0.5),
+                Token::FloatConstant(-0.5),
+                Token::FloatConstant(-0.25),
+                Token::RightBracket,
+            ])
+        );
+
+        // A lone `.` is not a number, so it is read as an identifier.
+        assert_eq!(
+            Reader::new(". -.").tokenise(),
+            Ok(vec![
+                Token::Identifier(".".into()),
+                Token::Identifier("-.".into()),
+            ])
+        );
+
+        assert_eq!(
+            Reader::new(".5.").tokenise(),
+            Err(ReaderError::ExpectedADigit(".5".into(), '.', 2))
+        );
+    }
+
+    #[test]
+    fn test_is_complete() {
+        assert!(is_complete(""));
+        assert!(is_complete("+ 1 2"));
+        assert!(is_complete("{set a 1 [a]}"));
+        assert!(is_complete("}"));
+        assert!(is_complete(r#""{""#));
+
+        assert!(!is_complete("{"));
+        assert!(!is_complete("{set a [1 2]"));
+        assert!(!is_complete("[{}"));
+        assert!(!is_complete(r#""abc"#));
+        assert!(!is_complete(r#"{"}"#));
+        assert!(!is_complete(r#""\""#));
+
+        assert!(is_complete(r#"r"\""#));
+        assert!(is_complete(r#"{r"}" r"\"}"#));
+        assert!(!is_complete(r#"r"abc"#));
+        assert!(!is_complete(r#"for"\""#));
+    }
+
+    #[test]
+    fn test_booleans() {
+        assert_eq!(
+            Reader::new("true false truthy [true]").tokenise(),
+            Ok(vec![
+                Token::Boolean(true),
+                Token::Boolean(false),
+                Token::Identifier("truthy".into()),
+                Token::LeftBracket,
+                Token::Boolean(true),
+                Token::RightBracket,
+            ])
+        );
+    }
+
+    #[test]
+    fn test_large_input() {
+        let input = "set x [1 -2.5 \"ü\"] ".repeat(20_000);
+        let tokens = Reader::new(&input).tokenise().unwrap();
+
+        assert_eq!(tokens.len(), 7 * 20_000);
+
+        for chunk in tokens.chunks(7) {
+            assert_eq!(chunk[0], Token::Identifier("set".into()));
+            assert_eq!(chunk[1], Token::Identifier("x".into()));
+            assert_eq!(chunk[2], Token::LeftBracket);
+            assert_eq!(chunk[3], Token::IntegerConstant(1));
+            assert_eq!(chunk[4], Token::FloatConstant(-2.5));
+            assert_eq!(chunk[5], Token::StringConstant("ü".into()));
+            assert_eq!(chunk[6], Token::RightBracket);
+        }
+    }
+
+    #[test]
+    fn test_long_input() {
+        let pieces = ["{+ 12 -3}", "\"a\\\"b\"", "[.5 true]", "defined? x"];
+        let input: String = pieces
+            .iter()
+            .cycle()
+            .take(12_000)
+            .map(|piece| format!("{} ", piece))
+            .collect();
+
+        assert!(input.chars().count() >= 100_000);
+
+        let expected: Vec<Token> = pieces
+            .iter()
+            .cycle()
+            .take(12_000)
+            .flat_map(|piece| Reader::new(piece).tokenise().unwrap())
+            .collect();
+
+        assert_eq!(Reader::new(&input).tokenise().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_token_stream() {
+        let mut tokens = TokenStream::new("set a [1]");
+
+        assert_eq!(tokens.peek(), Ok(Some(&Token::Identifier("set".into()))));
+        assert_eq!(tokens.next_token(), Ok(Token::Identifier("set".into())));
+        assert_eq!(tokens.next_token(), Ok(Token::Identifier("a".into())));
+        assert_eq!(tokens.next_token(), Ok(Token::LeftBracket));
+        assert_eq!(tokens.is_empty(), Ok(false));
+        assert_eq!(tokens.next_token(), Ok(Token::IntegerConstant(1)));
+        assert_eq!(tokens.next_token(), Ok(Token::RightBracket));
+        assert_eq!(tokens.is_empty(), Ok(true));
+        assert_eq!(tokens.next_token(), Err(ReaderError::EoF));
+
+        let mut tokens = TokenStream::new("a é");
+
+        assert_eq!(tokens.next_token(), Ok(Token::Identifier("a".into())));
+        assert_eq!(tokens.peek(), Err(ReaderError::UnexpectedCharacter('é')));
+
+        assert_eq!(TokenStream::new("  ").is_empty(), Ok(true));
+
+        let mut tokens = TokenStream::new("a b\n  c d");
+
+        assert_eq!(tokens.starts_line(), Ok(false));
+        assert_eq!(tokens.next_token(), Ok(Token::Identifier("a".into())));
+        assert_eq!(tokens.starts_line(), Ok(false));
+        assert_eq!(tokens.next_token(), Ok(Token::Identifier("b".into())));
+        assert_eq!(tokens.starts_line(), Ok(true));
+        assert_eq!(tokens.next_token(), Ok(Token::Identifier("c".into())));
+        assert_eq!(tokens.starts_line(), Ok(false));
+
+        let programs = [
+            "+ + 0.5 -1.0 -1",
+            "{set a [1 a][]} let b 2 b",
+            r#"if true then "a b\n" else .5"#,
+            "def fac [n] if < n 2 then 1 else * n fac - n 1",
+        ];
+
+        for program in programs {
+            let mut stream = TokenStream::new(program);
+            let mut streamed = Vec::new();
+
+            while !stream.is_empty().unwrap() {
+                streamed.push(stream.next_token().unwrap());
+            }
+
+            assert_eq!(streamed, Reader::new(program).tokenise().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_separators() {
+        let identifier = |name: &str| Token::Identifier(name.into());
+
+        assert_eq!(
+            Reader::new("set a 1;a;;").tokenise(),
+            Ok(vec![
+                identifier("set"),
+                identifier("a"),
+                Token::IntegerConstant(1),
+                Token::Terminator,
+                identifier("a"),
+                Token::Terminator,
+                Token::Terminator,
+            ])
+        );
+
+        assert_eq!(
+            Reader::new("+1 2").tokenise(),
+            Reader::new("+ 1 2").tokenise()
+        );
+
+        assert_eq!(
+            Reader::new("set\u{A0}a\u{B}1\u{C}a\u{2003}").tokenise(),
+            Ok(vec![
+                identifier("set"),
+                identifier("a"),
+                Token::IntegerConstant(1),
+                identifier("a"),
+            ])
+        );
+
+        assert_eq!(
+            Reader::new("<=2.5 -1").tokenise(),
+            Ok(vec![
+                identifier("<="),
+                Token::FloatConstant(2.5),
+                Token::IntegerConstant(-1),
+            ])
+        );
+
+        // A leading `-` still makes a negative number, and identifiers with
+        // letters in them keep their digits.
+        assert_eq!(
+            Reader::new("-1 x2 a-1 --1").tokenise(),
+            Ok(vec![
+                Token::IntegerConstant(-1),
+                identifier("x2"),
+                identifier("a-1"),
+                identifier("--"),
+                Token::IntegerConstant(1),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let input = "def f [x] {+ x 1.5} print \"héllo\\n wörld\" f -2 ; [true]";
+
+        assert_eq!(
+            Reader::from_reader(Cursor::new(input.to_string())).tokenise(),
+            Reader::new(input).tokenise()
+        );
+
+        assert_eq!(
+            Reader::from_reader(Cursor::new(b"\"\xFF\"".to_vec())).tokenise(),
+            Err(ReaderError::IoFailed("input is not valid UTF-8".into()))
+        );
+
+        assert_eq!(
+            Reader::from_reader(Cursor::new(b"\"\xC3".to_vec())).tokenise(),
+            Err(ReaderError::IoFailed("input is not valid UTF-8".into()))
+        );
+
+        assert_eq!(Reader::from_reader(Cursor::new("")).tokenise(), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_error_display() {
+        assert_eq!(ReaderError::EoF.to_string(), "Unexpected end of file");
+
+        assert_eq!(
+            ReaderError::UnexpectedCharacter('é').to_string(),
+            "Unexpected character: 'é'"
+        );
+
+        assert_eq!(
+            ReaderError::UnknownEscape('q').to_string(),
+            "Unknown escape sequence: '\\q'"
+        );
+
+        assert_eq!(
+            Reader::new("12x3").tokenise().unwrap_err().to_string(),
+            "Expected a digit after '12', found 'x' at position 2"
+        );
+
+        assert_eq!(
+            Reader::new("1.2y").tokenise().unwrap_err().to_string(),
+            "Expected a digit after '1.2', found 'y' at position 3"
+        );
+
+        assert_eq!(
+            Reader::new("1.").tokenise().unwrap_err().to_string(),
+            "Expected a digit after '1.', found the end of input"
+        );
+
+        assert_eq!(
+            ReaderError::NumberOutOfRange("-99999999999999999999".into()).to_string(),
+            "Integer literal -99999999999999999999 is out of range"
+        );
+    }
+
+    #[test]
+    fn test_characters() {
+        assert_eq!(
+            Reader::new("'a' '\\n' '\\'' 'é' ['{']").tokenise(),
+            Ok(vec![
+                Token::CharConstant('a'),
+                Token::CharConstant('\n'),
+                Token::CharConstant('\''),
+                Token::CharConstant('é'),
+                Token::LeftBracket,
+                Token::CharConstant('{'),
+                Token::RightBracket,
+            ])
+        );
+
+        // A quote inside an identifier doesn't start a character literal.
+        assert_eq!(
+            Reader::new("x' 'y'").tokenise(),
+            Ok(vec![
+                Token::Identifier("x'".into()),
+                Token::CharConstant('y')
+            ])
+        );
+
+        assert_eq!(
+            Reader::new("'a").tokenise(),
+            Err(ReaderError::UnterminatedCharacter)
+        );
+
+        assert_eq!(
+            Reader::new("'ab'").tokenise(),
+            Err(ReaderError::UnterminatedCharacter)
+        );
+
+        assert_eq!(
+            Reader::new("'\\").tokenise(),
+            Err(ReaderError::UnterminatedCharacter)
+        );
+
+        assert_eq!(
+            Reader::new("''").tokenise(),
+            Err(ReaderError::UnexpectedCharacter('\''))
+        );
+
+        assert_eq!(
+            Reader::new("'\\q'").tokenise(),
+            Err(ReaderError::UnknownEscape('q'))
+        );
+
+        assert!(is_complete("'{'"));
+        assert!(is_complete("['\\'' ']']"));
+        assert!(!is_complete("['['"));
+    }
 }